@@ -1,5 +1,17 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use custom_ecs::table::*;
+use custom_ecs::{storage::*, table::*, *};
+
+#[derive(Component)]
+struct Position {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Component)]
+struct Velocity {
+    x: f32,
+    y: f32,
+}
 
 pub fn table_test() -> Result<(), TableError> {
     let mut new_table = Table::new();
@@ -13,8 +25,61 @@ pub fn table_test() -> Result<(), TableError> {
     Ok(())
 }
 
+pub fn storage_test(mut world: World) {
+    for index in 0..1000 {
+        let entity = world.alloc_entity();
+        world
+            .enable_component_for_entity(entity, Position { x: 0.0, y: 0.0 })
+            .unwrap();
+        if index % 2 == 0 {
+            world
+                .enable_component_for_entity(entity, Velocity { x: 1.0, y: 1.0 })
+                .unwrap();
+        }
+    }
+
+    for bundle in world.component_node_bundles(
+        Some(component_set!(Position, Velocity)),
+        None,
+        None,
+    ) {
+        let velocity = world.unpack::<Velocity>(&bundle).map(|velocity| (velocity.x, velocity.y));
+        if let (Some(position), Some((x, y))) = (world.unpack_mut::<Position>(&bundle), velocity) {
+            position.x += x;
+            position.y += y;
+        }
+    }
+}
+
+pub fn spawn_batch_test(mut world: World) {
+    world
+        .spawn_batch((0..1000).map(|_| (Position { x: 0.0, y: 0.0 }, Velocity { x: 1.0, y: 1.0 })))
+        .unwrap();
+}
+
+pub fn entity_churn_test(mut world: World) {
+    let mut entities: Vec<Entity> = (0..1000).map(|_| world.alloc_entity()).collect();
+    for round in 0..10 {
+        // Despawn every other entity, fragmenting the free ids, then fill the gaps again
+        for entity in entities.iter().skip(round % 2).step_by(2) {
+            world.dealloc_entity(*entity).unwrap();
+        }
+        for index in (round % 2..entities.len()).step_by(2) {
+            entities[index] = world.alloc_entity();
+        }
+    }
+}
+
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("table_test", |b| b.iter(table_test));
+    c.bench_function("table_storage_test", |b| {
+        b.iter(|| storage_test(World::with_storage(TableStorage::new())))
+    });
+    c.bench_function("sparse_set_storage_test", |b| {
+        b.iter(|| storage_test(World::with_storage(SparseSetStorage::new())))
+    });
+    c.bench_function("spawn_batch_test", |b| b.iter(|| spawn_batch_test(World::new())));
+    c.bench_function("entity_churn_test", |b| b.iter(|| entity_churn_test(World::new())));
 }
 
 criterion_group!(benches, criterion_benchmark);
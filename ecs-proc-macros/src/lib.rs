@@ -1,37 +1,186 @@
 use std::str::FromStr;
 
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
-use rand::{thread_rng, Rng};
-use syn::{parse_macro_input, DeriveInput, Expr, Ident, LitStr, PatStruct};
+use quote::quote;
+use syn::{
+    parenthesized, parse_macro_input, parse_quote, punctuated::Punctuated, Attribute, DeriveInput,
+    Expr, Ident, LitStr, Token, Type,
+};
 
-#[proc_macro_derive(Component)]
+#[derive(Default)]
+struct ComponentAttributes {
+    id: Option<Expr>,
+    name: Option<LitStr>,
+    clone: bool,
+    debug: bool,
+    default: bool,
+    serde: bool,
+    on_add: Option<Expr>,
+    on_replace: Option<Expr>,
+    on_remove: Option<Expr>,
+    requires: Vec<Type>,
+    immutable: bool,
+    exclusive: Option<LitStr>,
+}
+
+impl ComponentAttributes {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut attributes = Self::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("component")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    attributes.id = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("name") {
+                    attributes.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("clone") {
+                    attributes.clone = true;
+                } else if meta.path.is_ident("debug") {
+                    attributes.debug = true;
+                } else if meta.path.is_ident("default") {
+                    attributes.default = true;
+                } else if meta.path.is_ident("serde") {
+                    attributes.serde = true;
+                } else if meta.path.is_ident("exclusive") {
+                    attributes.exclusive = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("immutable") {
+                    attributes.immutable = true;
+                } else if meta.path.is_ident("requires") {
+                    let content;
+                    parenthesized!(content in meta.input);
+                    attributes
+                        .requires
+                        .extend(Punctuated::<Type, Token![,]>::parse_terminated(&content)?);
+                } else if meta.path.is_ident("on_add") {
+                    attributes.on_add = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("on_replace") {
+                    attributes.on_replace = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("on_remove") {
+                    attributes.on_remove = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unsupported component attribute"));
+                }
+                Ok(())
+            })?;
+        }
+
+        if let (Some(_), Some(name)) = (&attributes.id, &attributes.name) {
+            return Err(syn::Error::new(
+                name.span(),
+                "`id` and `name` cannot both be set on a component",
+            ));
+        }
+
+        Ok(attributes)
+    }
+}
+
+#[proc_macro_derive(Component, attributes(component))]
 pub fn component(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
-    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let attributes = match ComponentAttributes::parse(&input.attrs) {
+        Ok(attributes) => attributes,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    // Components are stored as `dyn Any` and shared across threads, so every generic parameter
+    // has to be `'static`, and type arguments `Send + Sync`. Type arguments also need a stable
+    // hash to be mixed into the component's.
+    let mut generics = input.generics.clone();
+    let static_bounds: Vec<syn::WherePredicate> = generics
+        .lifetimes()
+        .map(|param| {
+            let lifetime = &param.lifetime;
+            parse_quote! { #lifetime: 'static }
+        })
+        .chain(generics.type_params().map(|param| {
+            let ident = &param.ident;
+            parse_quote! { #ident: 'static + Send + Sync + ::custom_ecs::component::TypeHash }
+        }))
+        .collect();
+    generics
+        .make_where_clause()
+        .predicates
+        .extend(static_bounds);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let name = input.ident;
-    let mut component_string = name.to_string();
+    let name_string = name.to_string();
 
-    let data_string = match input.data {
-        syn::Data::Struct(data) => data.fields.to_token_stream().to_string(),
-        syn::Data::Enum(data) => data.variants.to_token_stream().to_string(),
-        syn::Data::Union(_) => String::new(),
+    let component_name = match &attributes.name {
+        Some(component_name) => quote! { #component_name },
+        None => quote! { concat!(module_path!(), "::", #name_string) },
     };
 
-    component_string.push_str(&data_string);
+    // The id is derived from the fully qualified path of the type (or the name override),
+    // so it only changes when the type is moved or renamed.
+    let base_hash = match &attributes.id {
+        Some(id) => quote! { #id },
+        None => quote! { ::custom_ecs::component::component_hash(#component_name) },
+    };
 
-    let mut rng = thread_rng();
-    let rand_char = rng.gen::<char>();
-    component_string.push(rand_char);
+    // Generic arguments are mixed into the base hash, so `Buffer<A>` and `Buffer<B>` differ.
+    let type_arguments = input.generics.type_params().map(|param| {
+        let ident = &param.ident;
+        quote! {
+            let hash = ::custom_ecs::component::component_hash_combine(
+                hash,
+                <#ident as ::custom_ecs::component::TypeHash>::type_hash(),
+            );
+        }
+    });
+    let const_arguments = input.generics.const_params().map(|param| {
+        let ident = &param.ident;
+        quote! {
+            let hash = ::custom_ecs::component::component_hash_combine(hash, #ident as usize);
+        }
+    });
 
-    let component_bytes = component_string.as_bytes();
+    let hash = quote! {
+        const HASH: usize = #base_hash;
+        let hash = HASH;
+        #(#type_arguments)*
+        #(#const_arguments)*
+        hash
+    };
 
-    let mut hash: usize = 5381;
+    // Without a name override, generic components are named after their concrete type, generic
+    // arguments included.
+    let component_name = if input.generics.params.is_empty() || attributes.name.is_some() {
+        component_name
+    } else {
+        quote! { ::std::any::type_name::<Self>() }
+    };
 
-    for c in component_bytes.iter() {
-        hash = ((hash << 6).wrapping_add(hash)).wrapping_add(*c as usize);
+    let mut configure = Vec::new();
+    if attributes.clone {
+        configure.push(quote! { let info = info.with_clone::<Self>(); });
+    }
+    if attributes.debug {
+        configure.push(quote! { let info = info.with_debug::<Self>(); });
+    }
+    if attributes.default {
+        configure.push(quote! { let info = info.with_default::<Self>(); });
+    }
+    // Serialization support only exists when `custom_ecs` is built with its `serde` feature.
+    if attributes.serde && cfg!(feature = "serde") {
+        configure.push(quote! { let info = info.with_serde::<Self>(); });
+    }
+    for required in attributes.requires.iter() {
+        configure.push(quote! { let info = info.with_required::<#required>(); });
+    }
+    if let Some(group) = &attributes.exclusive {
+        configure.push(quote! { let info = info.with_exclusive_group(#group); });
+    }
+    if let Some(hook) = &attributes.on_add {
+        configure.push(quote! { let info = info.with_on_add(#hook); });
+    }
+    if let Some(hook) = &attributes.on_replace {
+        configure.push(quote! { let info = info.with_on_replace(#hook); });
+    }
+    if let Some(hook) = &attributes.on_remove {
+        configure.push(quote! { let info = info.with_on_remove(#hook); });
     }
 
     // Build the output, possibly using quasi-quotation
@@ -41,6 +190,15 @@ pub fn component(input: TokenStream) -> TokenStream {
                 #hash
             }
 
+            fn name() -> &'static str where Self : Sized {
+                #component_name
+            }
+
+            fn configure(info: ::custom_ecs::registry::ComponentInfo) -> ::custom_ecs::registry::ComponentInfo where Self : Sized {
+                #(#configure)*
+                info
+            }
+
             fn as_any(&self) -> &dyn std::any::Any {
                 self
             }
@@ -48,9 +206,37 @@ pub fn component(input: TokenStream) -> TokenStream {
             fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
                 self
             }
+
+            fn into_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+                self
+            }
         }
     };
 
+    // Immutable components can only be replaced through `World::enable_component_for_entity`.
+    let mutable = if attributes.immutable {
+        quote! {}
+    } else {
+        quote! {
+            impl #impl_generics ::custom_ecs::component::MutableComponent for #name #ty_generics #where_clause {}
+        }
+    };
+
+    // Components can be the arguments of generic components, under their own hash.
+    let type_hash = quote! {
+        impl #impl_generics ::custom_ecs::component::TypeHash for #name #ty_generics #where_clause {
+            fn type_hash() -> usize {
+                <Self as ::custom_ecs::component::Component>::hash()
+            }
+        }
+    };
+
+    let expanded = quote! {
+        #expanded
+        #mutable
+        #type_hash
+    };
+
     // Hand the output tokens back to the compiler
     TokenStream::from(expanded)
 }
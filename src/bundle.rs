@@ -0,0 +1,72 @@
+use crate::{Component, ComponentId, Entity, World, WorldError};
+
+// Components that are enabled for an entity together, e.g. `(Position, Velocity)`.
+pub trait Bundle {
+    // Returns the ids of the bundle's components, in order.
+    fn register(world: &mut World) -> Result<Vec<ComponentId>, WorldError>
+    where
+        Self: Sized;
+
+    // Makes room for `additional` more values of every component in the bundle.
+    fn reserve(world: &mut World, additional: usize)
+    where
+        Self: Sized;
+
+    fn enable_for_entity(self, world: &mut World, entity: Entity) -> Result<(), WorldError>;
+
+    // Stores the values of every bundle for the freshly spawned entity at the same index, one
+    // component at a time. `components` are the ids `register` returned.
+    fn insert_batch(
+        bundles: Vec<Self>,
+        world: &mut World,
+        entities: &[Entity],
+        components: &[ComponentId],
+    ) where
+        Self: Sized;
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($component:ident $index:tt),+) => {
+        impl<$($component),+> Bundle for ($($component,)+)
+        where
+            $($component: Component + 'static),+
+        {
+            fn register(world: &mut World) -> Result<Vec<ComponentId>, WorldError> {
+                Ok(vec![$(world.register_component::<$component>()?),+])
+            }
+
+            fn reserve(world: &mut World, additional: usize) {
+                $(world.reserve_component::<$component>(additional);)+
+            }
+
+            #[allow(non_snake_case)]
+            fn enable_for_entity(self, world: &mut World, entity: Entity) -> Result<(), WorldError> {
+                let ($($component,)+) = self;
+                $(world.enable_component_for_entity(entity, $component)?;)+
+                Ok(())
+            }
+
+            fn insert_batch(
+                bundles: Vec<Self>,
+                world: &mut World,
+                entities: &[Entity],
+                components: &[ComponentId],
+            ) {
+                let mut values = ($(Vec::<$component>::with_capacity(bundles.len()),)+);
+                for bundle in bundles {
+                    $(values.$index.push(bundle.$index);)+
+                }
+                $(world.insert_spawned(components[$index], entities, values.$index);)+
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!(A 0);
+impl_bundle_for_tuple!(A 0, B 1);
+impl_bundle_for_tuple!(A 0, B 1, C 2);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
@@ -1,14 +1,207 @@
-use std::any::Any;
+use std::{
+    alloc::Layout,
+    any::{type_name, Any},
+    mem::{needs_drop, size_of},
+};
 
 pub use ecs_proc_macros::Component;
 
-pub trait Component {
+use crate::{registry::ComponentInfo, utils::aligned_bytes::AlignedBytes, Entity};
+
+// Components are reached from rayon's workers through a shared world, so they have to be
+// `Send` and `Sync`.
+pub trait Component: Send + Sync {
     fn hash() -> usize
     where
         Self: Sized;
 
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        type_name::<Self>()
+    }
+
+    // Fills in the optional registry metadata, e.g. from `#[component(clone, debug, default)]`.
+    fn configure(info: ComponentInfo) -> ComponentInfo
+    where
+        Self: Sized,
+    {
+        info
+    }
+
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+/// Implemented by the derive for every component not marked `#[component(immutable)]`.
+/// Only mutable components can be borrowed mutably from the world; immutable ones can only be
+/// replaced as a whole, so every change goes through events and hooks.
+///
+/// ```compile_fail
+/// use custom_ecs::*;
+///
+/// #[derive(Component)]
+/// #[component(immutable)]
+/// struct Frozen(u32);
+///
+/// let mut world = World::new();
+/// let entity = world.alloc_entity();
+/// world.enable_component_for_entity(entity, Frozen(1)).unwrap();
+/// world.entity_component_mut::<Frozen>(entity);
+/// ```
+pub trait MutableComponent: Component {}
+
+// A component's index among the components registered in a world, handed out in registration
+// order. Unlike hashes, ids are small and dense, so per-component data can live in arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComponentId(pub(crate) usize);
+
+impl ComponentId {
+    pub fn new(index: usize) -> ComponentId {
+        ComponentId(index)
+    }
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// djb2-style hash over the component's fully qualified name. It only depends on the
+// bytes of the name, so ids stay the same between compilations and across machines.
+pub const fn component_hash(name: &str) -> usize {
+    let bytes = name.as_bytes();
+    let mut hash: u64 = 5381;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        hash = (hash << 6).wrapping_add(hash).wrapping_add(bytes[i] as u64);
+        i += 1;
+    }
+
+    hash as usize
+}
+
+// Zero-sized components without drop glue are markers: they only occupy a table node and no
+// value is stored for them.
+pub(crate) const fn is_marker<T>() -> bool {
+    size_of::<T>() == 0 && !needs_drop::<T>()
+}
+
+// Folds a generic argument into the hash of a generic component, so every monomorphization
+// gets its own id.
+pub const fn component_hash_combine(hash: usize, argument: usize) -> usize {
+    let hash = hash as u64;
+    let combined = (hash << 6).wrapping_add(hash).wrapping_add(argument as u64);
+    (combined ^ (combined >> 29)) as usize
+}
+
+// A hash of a type that only depends on what the type is called, not on the compiler, so generic
+// components can mix the hashes of their arguments into their own. Derived for components, and
+// implemented here for primitives and common std types; other argument types implement it by
+// hand, e.g. with `component_hash` of a fixed name.
+pub trait TypeHash {
+    fn type_hash() -> usize;
+}
+
+macro_rules! impl_type_hash {
+    ($($ty:ty),+) => {
+        $(impl TypeHash for $ty {
+            fn type_hash() -> usize {
+                const HASH: usize = component_hash(stringify!($ty));
+                HASH
+            }
+        })+
+    };
+}
+
+impl_type_hash!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char, (),
+    String
+);
+
+impl TypeHash for &'static str {
+    fn type_hash() -> usize {
+        component_hash("&str")
+    }
+}
+
+impl TypeHash for Entity {
+    fn type_hash() -> usize {
+        component_hash("Entity")
+    }
+}
+
+macro_rules! impl_type_hash_for_generic {
+    ($($name:literal => $ty:ident),+) => {
+        $(impl<T> TypeHash for $ty<T>
+        where
+            T: TypeHash,
+        {
+            fn type_hash() -> usize {
+                component_hash_combine(component_hash($name), T::type_hash())
+            }
+        })+
+    };
+}
+
+impl_type_hash_for_generic!("Vec" => Vec, "Option" => Option, "Box" => Box);
+
+impl<T, const N: usize> TypeHash for [T; N]
+where
+    T: TypeHash,
+{
+    fn type_hash() -> usize {
+        component_hash_combine(component_hash_combine(component_hash("array"), T::type_hash()), N)
+    }
+}
+
+macro_rules! impl_type_hash_for_tuple {
+    ($($element:ident),+) => {
+        impl<$($element),+> TypeHash for ($($element,)+)
+        where
+            $($element: TypeHash),+
+        {
+            fn type_hash() -> usize {
+                let hash = component_hash("tuple");
+                $(let hash = component_hash_combine(hash, $element::type_hash());)+
+                hash
+            }
+        }
+    };
+}
+
+impl_type_hash_for_tuple!(A);
+impl_type_hash_for_tuple!(A, B);
+impl_type_hash_for_tuple!(A, B, C);
+impl_type_hash_for_tuple!(A, B, C, D);
+
+// The data of a component registered at runtime through `World::register_dynamic_component`.
+// It is addressed by the hash of its registered name rather than by `Component::hash`. The bytes
+// are aligned as the registered layout requires.
+#[derive(Debug, Clone, Component)]
+pub struct DynamicComponent {
+    pub(crate) hash: usize,
+    pub(crate) data: AlignedBytes,
+}
+
+impl DynamicComponent {
+    pub fn component_hash(&self) -> usize {
+        self.hash
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.data.layout()
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
 }
 
 /* pub trait ComponentGroup<'g> {}
@@ -3,27 +3,60 @@ use std::fmt::Debug;
 use crate::*;
 use hashbrown::HashSet;
 
+// The largest id an entity can be spawned with. Per-entity data is kept in arrays indexed by id,
+// so ids coming from outside, e.g. through `World::spawn_at`, are capped to keep those small.
+pub const MAX_ENTITY_ID: usize = (1 << 24) - 1;
+
+// An entity's id and the generation of that id it was spawned in. Ids are reused once their
+// entity is despawned, so the generation tells a stale handle apart from the id's new entity.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Entity(pub(crate) usize);
+pub struct Entity(pub(crate) usize, pub(crate) u32);
 
 impl Entity {
+    // The first generation of `id`
     pub fn new(id: usize) -> Entity {
-        Entity(id)
+        Entity(id, 0)
+    }
+
+    pub fn with_generation(id: usize, generation: u32) -> Entity {
+        Entity(id, generation)
+    }
+
+    pub fn id(self) -> usize {
+        self.0
+    }
+
+    pub fn generation(self) -> u32 {
+        self.1
     }
 }
 
 impl Debug for Entity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("Entity({:#x})", &self.0))
+        f.write_fmt(format_args!("Entity({:#x}v{})", &self.0, &self.1))
     }
 }
 
+// How fragmented the free entity ids are, see `World::entity_id_stats`. Despawning entities
+// leaves gaps of free ids below the ids that were never handed out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EntityIdStats {
+    // Runs of free ids below `unbounded_from`
+    pub free_ranges: usize,
+    pub free_ids: usize,
+    pub largest_free_range: usize,
+    // Every id from here on is free
+    pub unbounded_from: usize,
+}
+
 #[derive(Debug, Component, Clone)]
+#[component(clone, debug)]
 pub struct Children {
     pub(crate) children: HashSet<Entity>
 }
 
 #[derive(Debug, Component, Clone)]
+#[component(clone, debug)]
 pub struct Parent {
     pub(crate) parent: Entity
 }
\ No newline at end of file
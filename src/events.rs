@@ -1,10 +1,11 @@
 use std::fmt::Debug;
 
-use crate::{Component, Entity};
+use crate::{Component, ComponentId, Entity};
 
+#[allow(unused_parens)]
 pub enum ECSEvent {
     EntitySpawned(Entity),
-    ComponentAdded(Entity, usize),
+    ComponentAdded(Entity, ComponentId),
     ComponentChanged(Entity, Box<(dyn Component + 'static)>),
     ComponentRemoved(Entity, Box<(dyn Component + 'static)>),
     EntityDespawned(Entity),
@@ -1,14 +1,27 @@
 #![crate_type = "lib"]
 #![allow(dead_code, unused_macros, unused_macro_rules)]
+// Lets the derive macros refer to this crate by name from inside the crate as well.
+extern crate self as custom_ecs;
+
+pub mod bundle;
 pub mod component;
 pub mod entity;
 pub mod events;
 pub mod macros;
+pub mod query;
+pub mod registry;
+pub mod storage;
 pub mod table;
 pub(crate) mod utils;
 pub mod world;
 
+pub use bundle::Bundle;
 pub use component::*;
 pub use entity::*;
 pub use hashbrown;
+pub use query::{Query, QueryData, ReadOnlyQueryData};
+pub use registry::{
+    ComponentHook, ComponentHooks, ComponentInfo, ComponentRegistry, ExclusiveGroup,
+    ExclusivityPolicy,
+};
 pub use world::*;
@@ -0,0 +1,289 @@
+use std::{marker::PhantomData, ptr::NonNull};
+
+use hashbrown::HashSet;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{
+    component::is_marker,
+    storage::{Column, ComponentFilter, ComponentStorage}, Component, Entity,
+    MutableComponent, World,
+};
+
+// The component references a query yields for every match, e.g. `(&Position, &mut Velocity)`.
+pub trait QueryData {
+    type Item<'w>: Send;
+    type Fetch<'w>: Copy + Send + Sync;
+
+    // Adds the hashes of the components every match must have.
+    fn component_hashes(hashes: &mut Vec<usize>);
+
+    /// Looks up the columns the query reads, once per query. Returns `None` if nothing can match,
+    /// e.g. because a queried type isn't the one registered under its hash.
+    ///
+    /// # Safety
+    ///
+    /// `world` must be valid for `'w`. Mutable references may only be fetched if the world is
+    /// borrowed mutably for `'w`.
+    unsafe fn init_fetch<'w>(world: NonNull<World>) -> Option<Self::Fetch<'w>>;
+
+    /// # Safety
+    ///
+    /// `entity` must have every component of `component_hashes`, and be fetched at most once per
+    /// `fetch`.
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity) -> Option<Self::Item<'w>>;
+}
+
+/// Queries that only read, so they can run on a shared world.
+///
+/// # Safety
+///
+/// Implementors must not hand out mutable references.
+pub unsafe trait ReadOnlyQueryData: QueryData {}
+
+impl<T> QueryData for &T
+where
+    T: Component + 'static,
+{
+    type Item<'w> = &'w T;
+    // `None` for markers, which have no column
+    type Fetch<'w> = Option<&'w Column<T>>;
+
+    fn component_hashes(hashes: &mut Vec<usize>) {
+        hashes.push(T::hash());
+    }
+
+    unsafe fn init_fetch<'w>(world: NonNull<World>) -> Option<Self::Fetch<'w>> {
+        let world = world.as_ref();
+        let component = world.component_id::<T>()?;
+        if is_marker::<T>() {
+            Some(None)
+        } else {
+            world.column::<T>(component).map(Some)
+        }
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity) -> Option<Self::Item<'w>> {
+        match fetch {
+            Some(column) => column.get(entity),
+            // SAFETY: see `World::component_ref`, the entity has the marker.
+            None => Some(NonNull::<T>::dangling().as_ref()),
+        }
+    }
+}
+
+unsafe impl<T> ReadOnlyQueryData for &T where T: Component + 'static {}
+
+// Hands out mutable references to the values of distinct entities, possibly on several threads
+// at once. Only the column's index is read through `column`, the values are reached through
+// `values` so no two references to the whole column are ever mutable.
+pub struct ColumnMut<'w, T> {
+    column: NonNull<Column<T>>,
+    values: NonNull<T>,
+    world: PhantomData<&'w mut Column<T>>,
+}
+
+impl<T> Clone for ColumnMut<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ColumnMut<'_, T> {}
+
+// SAFETY: every thread only gets to the values of the entities it fetches, and queries fetch each
+// entity once, so sharing the column only moves `T`s between threads.
+unsafe impl<T> Send for ColumnMut<'_, T> where T: Send {}
+unsafe impl<T> Sync for ColumnMut<'_, T> where T: Send {}
+
+impl<T> QueryData for &mut T
+where
+    T: MutableComponent + 'static,
+{
+    type Item<'w> = &'w mut T;
+    type Fetch<'w> = Option<ColumnMut<'w, T>>;
+
+    fn component_hashes(hashes: &mut Vec<usize>) {
+        hashes.push(T::hash());
+    }
+
+    unsafe fn init_fetch<'w>(world: NonNull<World>) -> Option<Self::Fetch<'w>> {
+        let world = &mut *world.as_ptr();
+        let component = world.component_id::<T>()?;
+        if is_marker::<T>() {
+            Some(None)
+        } else {
+            world.column_mut::<T>(component).map(|column| {
+                Some(ColumnMut {
+                    values: column.values_ptr(),
+                    column: NonNull::from(column),
+                    world: PhantomData,
+                })
+            })
+        }
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity) -> Option<Self::Item<'w>> {
+        match fetch {
+            // SAFETY: the world is borrowed mutably for `'w` and no other part of the query
+            // reads this column, so each entity's value is handed out once.
+            Some(column) => {
+                let index = column.column.as_ref().dense_index(entity.0)?;
+                Some(&mut *column.values.as_ptr().add(index))
+            }
+            None => Some(NonNull::<T>::dangling().as_mut()),
+        }
+    }
+}
+
+macro_rules! impl_query_data_for_tuple {
+    ($($data:ident),+) => {
+        impl<$($data),+> QueryData for ($($data,)+)
+        where
+            $($data: QueryData),+
+        {
+            type Item<'w> = ($($data::Item<'w>,)+);
+            type Fetch<'w> = ($($data::Fetch<'w>,)+);
+
+            fn component_hashes(hashes: &mut Vec<usize>) {
+                $($data::component_hashes(hashes);)+
+            }
+
+            unsafe fn init_fetch<'w>(world: NonNull<World>) -> Option<Self::Fetch<'w>> {
+                Some(($($data::init_fetch(world)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity) -> Option<Self::Item<'w>> {
+                let ($($data,)+) = fetch;
+                Some(($($data::fetch($data, entity)?,)+))
+            }
+        }
+
+        unsafe impl<$($data),+> ReadOnlyQueryData for ($($data,)+)
+        where
+            $($data: ReadOnlyQueryData),+
+        {}
+    };
+}
+
+impl_query_data_for_tuple!(A);
+impl_query_data_for_tuple!(A, B);
+impl_query_data_for_tuple!(A, B, C);
+impl_query_data_for_tuple!(A, B, C, D);
+impl_query_data_for_tuple!(A, B, C, D, E);
+impl_query_data_for_tuple!(A, B, C, D, E, F);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G, H);
+
+// Streams the matches of a query. The columns are looked up and the filter is set up when the
+// query is created; iterating allocates nothing.
+pub struct Query<'w, Q>
+where
+    Q: QueryData,
+{
+    storage: &'w dyn ComponentStorage,
+    filter: ComponentFilter,
+    entities: Box<dyn Iterator<Item = Entity> + 'w>,
+    fetch: Option<Q::Fetch<'w>>,
+    started: bool,
+    world: PhantomData<&'w World>,
+}
+
+impl<'w, Q> Query<'w, Q>
+where
+    Q: QueryData,
+{
+    // SAFETY: see `QueryData::init_fetch`.
+    pub(crate) unsafe fn new(
+        world: NonNull<World>,
+        with: Option<HashSet<usize>>,
+        without: Option<HashSet<usize>>,
+    ) -> Self {
+        let mut hashes = Vec::new();
+        Q::component_hashes(&mut hashes);
+
+        let required = hashes.into_iter().chain(with.into_iter().flatten());
+        let filter = world
+            .as_ref()
+            .component_filter(required, without.into_iter().flatten());
+        // Without a filter nothing can match, so there is nothing to fetch either.
+        let fetch = filter.as_ref().and_then(|_| Q::init_fetch(world));
+        let filter = filter.unwrap_or_default();
+
+        // The entity walk only reads the storage's index, never the columns fetched above.
+        let storage = world.as_ref().storage();
+        let entities = storage.matching_entities(&filter);
+
+        Self {
+            storage,
+            filter,
+            entities,
+            fetch,
+            started: false,
+            world: PhantomData,
+        }
+    }
+
+    // Number of matches left. Until the query is iterated, the storage counts them from its
+    // index without visiting any component.
+    pub fn count(self) -> usize {
+        match (&self.fetch, self.started) {
+            (None, _) => 0,
+            (Some(_), false) => self.storage.count_matching(&self.filter),
+            (Some(_), true) => Iterator::count(self),
+        }
+    }
+
+    // Whether nothing matches the query, however far it has been iterated.
+    pub fn is_empty(&self) -> bool {
+        self.fetch.is_none() || !self.storage.any_matching(&self.filter)
+    }
+}
+
+#[cfg(feature = "rayon")]
+const PAR_CHUNK_SIZE: usize = 64;
+
+#[cfg(feature = "rayon")]
+impl<'w, Q> Query<'w, Q>
+where
+    Q: QueryData + 'w,
+{
+    // Splits the remaining matches into chunks that rayon's workers fetch in parallel. Queries
+    // fetch every entity once, so the workers only ever borrow disjoint components.
+    pub fn par_iter(self) -> impl ParallelIterator<Item = (Entity, Q::Item<'w>)> + 'w {
+        let fetch = self.fetch;
+        let entities: Vec<Entity> = match fetch {
+            Some(_) => self.entities.collect(),
+            None => Vec::new(),
+        };
+
+        entities
+            .into_par_iter()
+            .with_min_len(PAR_CHUNK_SIZE)
+            .filter_map(move |entity| {
+                let mut fetch = fetch?;
+                // SAFETY: the walk only yields entities with every queried component, each once.
+                unsafe { Q::fetch(&mut fetch, entity) }.map(|item| (entity, item))
+            })
+    }
+}
+
+impl<'w, Q> Iterator for Query<'w, Q>
+where
+    Q: QueryData,
+{
+    type Item = (Entity, Q::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.started = true;
+        let fetch = self.fetch.as_mut()?;
+        for entity in self.entities.by_ref() {
+            // SAFETY: the walk only yields entities with every queried component, each once.
+            if let Some(item) = unsafe { Q::fetch(fetch, entity) } {
+                return Some((entity, item));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,651 @@
+use std::{
+    alloc::Layout,
+    any::{type_name, TypeId},
+    borrow::Cow,
+    fmt::Debug,
+    ptr::NonNull,
+};
+
+use crate::{
+    component::is_marker,
+    component_hash,
+    storage::{Column, ComponentColumn},
+    Component, ComponentId, DynamicComponent, Entity, World, WorldError,
+};
+use hashbrown::HashMap;
+
+pub type CloneFn = fn(&dyn Component) -> Box<dyn Component>;
+pub type DebugFn = fn(&dyn Component, &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+pub type DefaultFn = fn() -> Box<dyn Component>;
+#[cfg(feature = "serde")]
+pub type SerializeFn = fn(&dyn Component) -> &dyn erased_serde::Serialize;
+#[cfg(feature = "serde")]
+pub type DeserializeFn =
+    fn(&mut dyn erased_serde::Deserializer) -> Result<Box<dyn Component>, erased_serde::Error>;
+
+pub type ComponentHook = fn(&mut World, Entity);
+
+// Callbacks that run synchronously while the world changes a component of an entity.
+// `on_add` and `on_replace` run after the new value is stored, `on_remove` before the value is dropped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ComponentHooks {
+    pub on_add: Option<ComponentHook>,
+    pub on_replace: Option<ComponentHook>,
+    pub on_remove: Option<ComponentHook>,
+}
+
+// A component that is inserted with its default value whenever its dependent is enabled on an
+// entity that doesn't have it yet.
+#[derive(Debug, Clone, Copy)]
+pub struct RequiredComponent {
+    type_id: TypeId,
+    info: fn() -> ComponentInfo,
+    default_fn: DefaultFn,
+}
+
+impl RequiredComponent {
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn info(&self) -> ComponentInfo {
+        (self.info)()
+    }
+
+    pub fn default_component(&self) -> Box<dyn Component> {
+        (self.default_fn)()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusivityPolicy {
+    // Enabling a member removes every other member from the entity.
+    #[default]
+    Replace,
+    // Enabling a member on an entity that has another member is an error.
+    Reject,
+}
+
+// A set of components of which an entity may have at most one at a time.
+#[derive(Debug, Clone)]
+pub struct ExclusiveGroup {
+    name: Cow<'static, str>,
+    members: Vec<ComponentId>,
+    policy: ExclusivityPolicy,
+}
+
+impl ExclusiveGroup {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn members(&self) -> &[ComponentId] {
+        &self.members
+    }
+
+    pub fn policy(&self) -> ExclusivityPolicy {
+        self.policy
+    }
+}
+
+#[derive(Clone)]
+pub struct ComponentInfo {
+    hash: usize,
+    name: Cow<'static, str>,
+    // Dynamic components have no Rust type behind them.
+    type_id: Option<TypeId>,
+    type_name: Option<&'static str>,
+    layout: Layout,
+    hooks: ComponentHooks,
+    required: Vec<RequiredComponent>,
+    exclusive_group: Option<Cow<'static, str>>,
+    // Produces a value of a marker component, which isn't stored anywhere.
+    marker_fn: Option<DefaultFn>,
+    column_fn: fn() -> Box<dyn ComponentColumn>,
+    clone_fn: Option<CloneFn>,
+    debug_fn: Option<DebugFn>,
+    default_fn: Option<DefaultFn>,
+    #[cfg(feature = "serde")]
+    serialize_fn: Option<SerializeFn>,
+    #[cfg(feature = "serde")]
+    deserialize_fn: Option<DeserializeFn>,
+}
+
+impl ComponentInfo {
+    // The metadata `T` declares, which can be extended before it is registered.
+    pub fn of<T>() -> Self
+    where
+        T: Component + 'static,
+    {
+        T::configure(Self::undeclared::<T>())
+    }
+
+    // `T`'s type information alone, what `Component::configure` starts from.
+    fn undeclared<T>() -> Self
+    where
+        T: Component + 'static,
+    {
+        Self {
+            hash: T::hash(),
+            name: Cow::Borrowed(T::name()),
+            type_id: Some(TypeId::of::<T>()),
+            type_name: Some(type_name::<T>()),
+            layout: Layout::new::<T>(),
+            hooks: ComponentHooks::default(),
+            required: Vec::new(),
+            exclusive_group: None,
+            marker_fn: is_marker::<T>().then_some(marker::<T> as DefaultFn),
+            column_fn: || Box::new(Column::<T>::new()),
+            clone_fn: None,
+            debug_fn: None,
+            default_fn: None,
+            #[cfg(feature = "serde")]
+            serialize_fn: None,
+            #[cfg(feature = "serde")]
+            deserialize_fn: None,
+        }
+    }
+
+    pub fn dynamic(name: impl Into<Cow<'static, str>>, layout: Layout) -> Self {
+        let name = name.into();
+        Self {
+            hash: component_hash(&name),
+            name,
+            type_id: None,
+            type_name: None,
+            layout,
+            hooks: ComponentHooks::default(),
+            required: Vec::new(),
+            exclusive_group: None,
+            marker_fn: None,
+            column_fn: || Box::new(Column::<DynamicComponent>::new()),
+            clone_fn: Some(|component| Box::new(downcast::<DynamicComponent>(component).clone())),
+            debug_fn: Some(|component, f| downcast::<DynamicComponent>(component).fmt(f)),
+            default_fn: None,
+            #[cfg(feature = "serde")]
+            serialize_fn: None,
+            #[cfg(feature = "serde")]
+            deserialize_fn: None,
+        }
+    }
+
+    pub fn with_clone<T>(mut self) -> Self
+    where
+        T: Component + Clone + 'static,
+    {
+        self.assert_type::<T>();
+        self.clone_fn = Some(|component| Box::new(downcast::<T>(component).clone()));
+        self
+    }
+
+    pub fn with_debug<T>(mut self) -> Self
+    where
+        T: Component + Debug + 'static,
+    {
+        self.assert_type::<T>();
+        self.debug_fn = Some(|component, f| downcast::<T>(component).fmt(f));
+        self
+    }
+
+    pub fn with_default<T>(mut self) -> Self
+    where
+        T: Component + Default + 'static,
+    {
+        self.assert_type::<T>();
+        self.default_fn = Some(|| Box::new(T::default()));
+        self
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn with_serde<T>(mut self) -> Self
+    where
+        T: Component + serde::Serialize + serde::de::DeserializeOwned + 'static,
+    {
+        self.assert_type::<T>();
+        self.serialize_fn = Some(|component| downcast::<T>(component));
+        self.deserialize_fn = Some(|deserializer| {
+            erased_serde::deserialize::<T>(deserializer)
+                .map(|component| Box::new(component) as Box<dyn Component>)
+        });
+        self
+    }
+
+    pub fn with_on_add(mut self, hook: ComponentHook) -> Self {
+        self.hooks.on_add = Some(hook);
+        self
+    }
+
+    pub fn with_on_replace(mut self, hook: ComponentHook) -> Self {
+        self.hooks.on_replace = Some(hook);
+        self
+    }
+
+    pub fn with_on_remove(mut self, hook: ComponentHook) -> Self {
+        self.hooks.on_remove = Some(hook);
+        self
+    }
+
+    pub fn with_required<R>(mut self) -> Self
+    where
+        R: Component + Default + 'static,
+    {
+        if !self
+            .required
+            .iter()
+            .any(|required| required.type_id == TypeId::of::<R>())
+        {
+            self.required.push(RequiredComponent {
+                type_id: TypeId::of::<R>(),
+                info: ComponentInfo::of::<R>,
+                default_fn: || Box::new(R::default()),
+            });
+        }
+        self
+    }
+
+    pub fn required(&self) -> &[RequiredComponent] {
+        &self.required
+    }
+
+    pub fn with_exclusive_group(mut self, group: impl Into<Cow<'static, str>>) -> Self {
+        self.exclusive_group = Some(group.into());
+        self
+    }
+
+    pub fn exclusive_group(&self) -> Option<&str> {
+        self.exclusive_group.as_deref()
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut ComponentHooks {
+        &mut self.hooks
+    }
+
+    pub fn hash(&self) -> usize {
+        self.hash
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.type_id
+    }
+
+    pub fn type_name(&self) -> Option<&'static str> {
+        self.type_name
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.type_id.is_none()
+    }
+
+    pub fn is_marker(&self) -> bool {
+        self.marker_fn.is_some()
+    }
+
+    pub(crate) fn marker(&self) -> Option<Box<dyn Component>> {
+        self.marker_fn.map(|marker_fn| marker_fn())
+    }
+
+    pub(crate) fn new_column_fn(&self) -> fn() -> Box<dyn ComponentColumn> {
+        self.column_fn
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    pub fn clone_component(&self, component: &dyn Component) -> Option<Box<dyn Component>> {
+        self.clone_fn.map(|clone_fn| clone_fn(component))
+    }
+
+    pub fn debug<'a>(&self, component: &'a dyn Component) -> Option<impl Debug + 'a> {
+        self.debug_fn.map(|debug_fn| ComponentDebug {
+            component,
+            debug_fn,
+        })
+    }
+
+    pub fn default_component(&self) -> Option<Box<dyn Component>> {
+        self.default_fn.map(|default_fn| default_fn())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn serialize<'a>(
+        &self,
+        component: &'a dyn Component,
+    ) -> Option<&'a dyn erased_serde::Serialize> {
+        self.serialize_fn
+            .map(|serialize_fn| serialize_fn(component))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn deserialize(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Option<Result<Box<dyn Component>, erased_serde::Error>> {
+        self.deserialize_fn
+            .map(|deserialize_fn| deserialize_fn(deserializer))
+    }
+
+    fn assert_type<T>(&self)
+    where
+        T: 'static,
+    {
+        assert_eq!(
+            self.type_id,
+            Some(TypeId::of::<T>()),
+            "component info for `{}` was given functions for `{}`",
+            self.display_name(),
+            type_name::<T>()
+        );
+    }
+
+    fn display_name(&self) -> String {
+        self.type_name.unwrap_or(&self.name).to_string()
+    }
+
+    // Typed components are the same if their types are, dynamic ones if name and layout match.
+    fn describes_same_component(&self, other: &ComponentInfo) -> bool {
+        self.type_id == other.type_id
+            && (self.type_id.is_some() || (self.name == other.name && self.layout == other.layout))
+    }
+}
+
+impl Debug for ComponentInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentInfo")
+            .field("hash", &format_args!("{:#x}", self.hash))
+            .field("name", &self.name)
+            .field("type_name", &self.type_name)
+            .field("layout", &self.layout)
+            .field("hooks", &self.hooks)
+            .field("exclusive_group", &self.exclusive_group)
+            .field(
+                "required",
+                &self
+                    .required
+                    .iter()
+                    .map(|required| required.info().display_name())
+                    .collect::<Vec<_>>(),
+            )
+            .field("clone", &self.clone_fn.is_some())
+            .field("debug", &self.debug_fn.is_some())
+            .field("default", &self.default_fn.is_some())
+            .finish()
+    }
+}
+
+struct ComponentDebug<'a> {
+    component: &'a dyn Component,
+    debug_fn: DebugFn,
+}
+
+impl Debug for ComponentDebug<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (self.debug_fn)(self.component, f)
+    }
+}
+
+fn marker<T>() -> Box<dyn Component>
+where
+    T: Component + 'static,
+{
+    // SAFETY: only used for markers, which are zero-sized and have no drop glue, and only once a
+    // value of the marker has been inserted, so reading "a" value out of no memory is sound.
+    Box::new(unsafe { NonNull::<T>::dangling().as_ptr().read() })
+}
+
+// The registry only hands a component to the functions of its own type, so this can't fail.
+fn downcast<T>(component: &dyn Component) -> &T
+where
+    T: 'static,
+{
+    component.as_any().downcast_ref::<T>().unwrap()
+}
+
+// A required component, resolved to the id it is registered under.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Requirement {
+    pub(crate) component: ComponentId,
+    pub(crate) default_fn: DefaultFn,
+}
+
+#[derive(Debug, Default)]
+pub struct ComponentRegistry {
+    // Indexed by component id
+    components: Vec<ComponentInfo>,
+    requirements: Vec<Vec<Requirement>>,
+    ids: HashMap<usize, ComponentId>,
+    exclusive_groups: HashMap<Cow<'static, str>, ExclusiveGroup>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers `T` with the metadata its derive declares. Registering it again is a no-op.
+    pub fn register<T>(&mut self) -> Result<ComponentId, WorldError>
+    where
+        T: Component + 'static,
+    {
+        let component_hash = T::hash();
+        match self.id_of(component_hash) {
+            Some(id) if self.components[id.0].type_id == Some(TypeId::of::<T>()) => Ok(id),
+            Some(id) => Err(WorldError::ComponentHashCollision {
+                hash: component_hash,
+                registered: self.components[id.0].display_name(),
+                rejected: type_name::<T>().to_string(),
+            }),
+            None => self.register_info(ComponentInfo::of::<T>()),
+        }
+    }
+
+    // Registers a runtime-defined component. Registering the same name and layout again is a no-op.
+    pub fn register_dynamic(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        layout: Layout,
+    ) -> Result<ComponentId, WorldError> {
+        let info = ComponentInfo::dynamic(name, layout);
+        match self.id_of(info.hash) {
+            Some(id) if self.components[id.0].describes_same_component(&info) => Ok(id),
+            _ => self.register_info(info),
+        }
+    }
+
+    // Registers or replaces the metadata of a component, and registers every component it
+    // requires that isn't registered yet.
+    pub fn register_info(&mut self, info: ComponentInfo) -> Result<ComponentId, WorldError> {
+        let required = info.required.clone();
+        let id = self.insert_info(info)?;
+
+        let mut requirements = Vec::with_capacity(required.len());
+        for required_component in required {
+            let required_info = required_component.info();
+            let required_id = match self.id_of(required_info.hash) {
+                Some(registered)
+                    if self.components[registered.0].describes_same_component(&required_info) =>
+                {
+                    registered
+                }
+                _ => self.register_info(required_info)?,
+            };
+            requirements.push(Requirement {
+                component: required_id,
+                default_fn: required_component.default_fn,
+            });
+        }
+        self.requirements[id.0] = requirements;
+
+        Ok(id)
+    }
+
+    fn insert_info(&mut self, info: ComponentInfo) -> Result<ComponentId, WorldError> {
+        let component_hash = info.hash;
+        if let Some(registered) = self.id_of(component_hash).map(|id| &self.components[id.0]) {
+            if !registered.describes_same_component(&info) {
+                return Err(WorldError::ComponentHashCollision {
+                    hash: component_hash,
+                    registered: registered.display_name(),
+                    rejected: info.display_name(),
+                });
+            }
+        }
+
+        let group = info.exclusive_group.clone();
+        // A replaced component keeps its id, a new one gets the next free id.
+        let id = match self.ids.get(&component_hash) {
+            Some(id) => {
+                let id = *id;
+                let old_info = std::mem::replace(&mut self.components[id.0], info);
+                if let Some(old_group) = old_info.exclusive_group {
+                    self.leave_exclusive_group(&old_group, id);
+                }
+                id
+            }
+            None => {
+                let id = ComponentId(self.components.len());
+                self.ids.insert(component_hash, id);
+                self.components.push(info);
+                self.requirements.push(Vec::new());
+                id
+            }
+        };
+        if let Some(group) = group {
+            self.exclusive_group_entry(group).members.push(id);
+        }
+        Ok(id)
+    }
+
+    // Puts the components into the group (creating it if needed) and sets the group's policy.
+    // Components already in another group leave it.
+    pub fn set_exclusive_group(
+        &mut self,
+        group: impl Into<Cow<'static, str>>,
+        members: &[ComponentId],
+        policy: ExclusivityPolicy,
+    ) -> Result<(), WorldError> {
+        let group = group.into();
+        if let Some(unregistered) = members.iter().find(|member| self.get(**member).is_none()) {
+            return Err(WorldError::UnregisteredComponent(*unregistered));
+        }
+
+        for member in members.iter() {
+            let info = self.get_mut(*member).unwrap();
+            if info.exclusive_group.as_deref() == Some(&group) {
+                continue;
+            }
+            if let Some(old_group) = info.exclusive_group.replace(group.clone()) {
+                self.leave_exclusive_group(&old_group, *member);
+            }
+            self.exclusive_group_entry(group.clone())
+                .members
+                .push(*member);
+        }
+        self.exclusive_group_entry(group).policy = policy;
+        Ok(())
+    }
+
+    pub fn exclusive_group(&self, group: &str) -> Option<&ExclusiveGroup> {
+        self.exclusive_groups.get(group)
+    }
+
+    pub fn exclusive_group_of(&self, component: ComponentId) -> Option<&ExclusiveGroup> {
+        self.get(component)
+            .and_then(|info| info.exclusive_group.as_deref())
+            .and_then(|group| self.exclusive_groups.get(group))
+    }
+
+    fn exclusive_group_entry(&mut self, group: Cow<'static, str>) -> &mut ExclusiveGroup {
+        self.exclusive_groups
+            .entry(group.clone())
+            .or_insert_with(|| ExclusiveGroup {
+                name: group,
+                members: Vec::new(),
+                policy: ExclusivityPolicy::default(),
+            })
+    }
+
+    fn leave_exclusive_group(&mut self, group: &str, component: ComponentId) {
+        if let Some(group) = self.exclusive_groups.get_mut(group) {
+            group.members.retain(|member| *member != component);
+        }
+    }
+
+    pub fn get(&self, component: ComponentId) -> Option<&ComponentInfo> {
+        self.components.get(component.0)
+    }
+
+    pub fn get_mut(&mut self, component: ComponentId) -> Option<&mut ComponentInfo> {
+        self.components.get_mut(component.0)
+    }
+
+    pub fn id_of(&self, component_hash: usize) -> Option<ComponentId> {
+        self.ids.get(&component_hash).copied()
+    }
+
+    // The components `component` requires directly.
+    pub(crate) fn requirements_of(&self, component: ComponentId) -> &[Requirement] {
+        self.requirements
+            .get(component.0)
+            .map_or(&[], |requirements| requirements.as_slice())
+    }
+
+    // Every component that enabling `component` pulls in, directly or through other requirements.
+    pub fn required_components_of(&self, component: ComponentId) -> Vec<ComponentId> {
+        let mut required: Vec<ComponentId> = Vec::new();
+        let mut pending: Vec<ComponentId> = vec![component];
+
+        while let Some(current) = pending.pop() {
+            for requirement in self.requirements_of(current) {
+                if requirement.component != component && !required.contains(&requirement.component)
+                {
+                    required.push(requirement.component);
+                    pending.push(requirement.component);
+                }
+            }
+        }
+
+        required
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&ComponentInfo> {
+        self.components.iter().find(|info| info.name == name)
+    }
+
+    pub fn is_registered_as<T>(&self) -> bool
+    where
+        T: Component + 'static,
+    {
+        self.id_of(T::hash())
+            .is_some_and(|id| self.components[id.0].type_id == Some(TypeId::of::<T>()))
+    }
+
+    // In id order
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.components.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
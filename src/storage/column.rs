@@ -0,0 +1,260 @@
+use std::{any::Any, mem, ptr::NonNull};
+
+use crate::{Component, ComponentId, Entity};
+
+const PAGE_SIZE: usize = 256;
+const EMPTY: usize = usize::MAX;
+
+// Contiguous storage for every value of one component type, as a sparse set: the values are
+// packed densely, and a paged sparse array maps entity ids to their index in the dense array.
+pub struct Column<T> {
+    values: Vec<T>,
+    entities: Vec<Entity>,
+    sparse: Vec<Option<Box<[usize; PAGE_SIZE]>>>,
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Column<T> {
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            entities: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Handles of despawned entities don't match the entity that reuses their id.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entity_index(entity).is_some()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+        self.entities.reserve(additional);
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.entity_index(entity)
+            .map(|index| &self.values[index])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.entity_index(entity)
+            .map(|index| &mut self.values[index])
+    }
+
+    // Lookups by id alone, for callers that already know the id belongs to a live entity
+    pub(crate) fn contains_id(&self, entity: usize) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub(crate) fn get_by_id(&self, entity: usize) -> Option<&T> {
+        self.dense_index(entity)
+            .map(|index| &self.values[index])
+    }
+
+    pub(crate) fn get_by_id_mut(&mut self, entity: usize) -> Option<&mut T> {
+        self.dense_index(entity)
+            .map(|index| &mut self.values[index])
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.entities.iter().copied().zip(self.values.iter_mut())
+    }
+
+    pub(crate) fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        if let Some(index) = self.dense_index(entity.0) {
+            self.entities[index] = entity;
+            return Some(mem::replace(&mut self.values[index], value));
+        }
+
+        self.set_dense_index(entity.0, self.values.len());
+        self.values.push(value);
+        self.entities.push(entity);
+        None
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.entities.clear();
+        self.sparse.clear();
+    }
+
+    pub(crate) fn remove(&mut self, entity: usize) -> Option<T> {
+        let index = self.dense_index(entity)?;
+        self.set_dense_index(entity, EMPTY);
+
+        let value = self.values.swap_remove(index);
+        self.entities.swap_remove(index);
+        // The last value was moved into the hole
+        if let Some(moved_entity) = self.entities.get(index).copied() {
+            self.set_dense_index(moved_entity.0, index);
+        }
+        Some(value)
+    }
+
+    // Base of the dense values, for handing out references to several values at once.
+    pub(crate) fn values_ptr(&mut self) -> NonNull<T> {
+        NonNull::new(self.values.as_mut_ptr()).expect("vector pointers are never null")
+    }
+
+    fn entity_index(&self, entity: Entity) -> Option<usize> {
+        self.dense_index(entity.0)
+            .filter(|index| self.entities[*index] == entity)
+    }
+
+    pub(crate) fn dense_index(&self, entity: usize) -> Option<usize> {
+        let page = self.sparse.get(entity / PAGE_SIZE)?.as_ref()?;
+        let index = page[entity % PAGE_SIZE];
+        (index != EMPTY).then_some(index)
+    }
+
+    fn set_dense_index(&mut self, entity: usize, index: usize) {
+        let page_index = entity / PAGE_SIZE;
+        if page_index >= self.sparse.len() {
+            self.sparse.resize_with(page_index + 1, || None);
+        }
+        let page = self.sparse[page_index].get_or_insert_with(|| Box::new([EMPTY; PAGE_SIZE]));
+        page[entity % PAGE_SIZE] = index;
+    }
+}
+
+// Type-erased access to a column, for the code paths that only know the component's hash.
+pub trait ComponentColumn: Send + Sync {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn contains(&self, entity: usize) -> bool;
+    fn reserve(&mut self, additional: usize);
+    fn insert_boxed(
+        &mut self,
+        entity: Entity,
+        component: Box<dyn Component>,
+    ) -> Option<Box<dyn Component>>;
+    fn remove_boxed(&mut self, entity: usize) -> Option<Box<dyn Component>>;
+    // Drops the values of `entities` without boxing them.
+    fn remove_entities(&mut self, entities: &[Entity]);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T> ComponentColumn for Column<T>
+where
+    T: Component + 'static,
+{
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn contains(&self, entity: usize) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Column::reserve(self, additional)
+    }
+
+    fn insert_boxed(
+        &mut self,
+        entity: Entity,
+        component: Box<dyn Component>,
+    ) -> Option<Box<dyn Component>> {
+        // Columns are created from the registry entry of their hash, which always matches the value.
+        let component = component
+            .into_any()
+            .downcast::<T>()
+            .expect("component does not match the type of its column");
+        self.insert(entity, *component)
+            .map(|old| Box::new(old) as Box<dyn Component>)
+    }
+
+    fn remove_boxed(&mut self, entity: usize) -> Option<Box<dyn Component>> {
+        self.remove(entity)
+            .map(|old| Box::new(old) as Box<dyn Component>)
+    }
+
+    fn remove_entities(&mut self, entities: &[Entity]) {
+        for entity in entities.iter() {
+            self.remove(entity.0);
+        }
+    }
+
+    fn clear(&mut self) {
+        Column::clear(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// The columns of a storage backend, indexed by component id.
+#[derive(Default)]
+pub(crate) struct ColumnMap {
+    columns: Vec<Option<Box<dyn ComponentColumn>>>,
+}
+
+impl ColumnMap {
+    pub(crate) fn get(&self, component: ComponentId) -> Option<&dyn ComponentColumn> {
+        self.columns.get(component.0)?.as_deref()
+    }
+
+    pub(crate) fn get_mut(&mut self, component: ComponentId) -> Option<&mut dyn ComponentColumn> {
+        match self.columns.get_mut(component.0) {
+            Some(Some(column)) => Some(column.as_mut()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_or_insert_with(
+        &mut self,
+        component: ComponentId,
+        new_column: &dyn Fn() -> Box<dyn ComponentColumn>,
+    ) -> &mut dyn ComponentColumn {
+        if component.0 >= self.columns.len() {
+            self.columns.resize_with(component.0 + 1, || None);
+        }
+        self.columns[component.0]
+            .get_or_insert_with(new_column)
+            .as_mut()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &dyn ComponentColumn> {
+        self.columns.iter().flatten().map(|column| column.as_ref())
+    }
+}
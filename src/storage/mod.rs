@@ -0,0 +1,75 @@
+mod column;
+mod sparse_set_storage;
+mod table_storage;
+
+pub use column::{Column, ComponentColumn};
+pub use sparse_set_storage::SparseSetStorage;
+pub use table_storage::TableStorage;
+
+use crate::{ComponentId, Entity};
+
+// The components an entity must have and must not have to match a storage query.
+#[derive(Debug, Default, Clone)]
+pub struct ComponentFilter {
+    pub required: Vec<ComponentId>,
+    pub excluded: Vec<ComponentId>,
+}
+
+// Where a world records which entities have which components, and keeps the values of those
+// that carry data. A world is created with one backend (`World::with_storage`) and only talks to
+// it through this trait, so backends can be swapped and benchmarked against each other.
+pub trait ComponentStorage: Send + Sync {
+    // Marks the component as enabled for `entity`, returning whether it already was.
+    fn enable(&mut self, entity: Entity, component: ComponentId) -> bool;
+
+    // Marks the component as disabled for `entity`, returning whether it was enabled.
+    // Its value, if it has one, stays in its column until the world removes it.
+    fn disable(&mut self, entity: Entity, component: ComponentId) -> bool;
+
+    // Disables the component for every entity that has it, returning those entities.
+    fn disable_all(&mut self, component: ComponentId) -> Vec<Entity>;
+
+    // Disables the component for every entity that matches the filter and has it, returning those
+    // entities.
+    fn disable_matching(&mut self, component: ComponentId, filter: &ComponentFilter) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.matching_entities(filter).collect();
+        entities.retain(|entity| self.disable(*entity, component));
+        entities
+    }
+
+    fn contains(&self, entity: Entity, component: ComponentId) -> bool;
+
+    // Every component enabled for `entity`.
+    fn components_of(&self, entity: Entity) -> Vec<ComponentId>;
+
+    // Lazily yields every entity that matches the filter, in an order of the backend's choosing.
+    // Only starting the iteration may allocate. Nothing matches a filter without requirements.
+    fn matching_entities(&self, filter: &ComponentFilter) -> Box<dyn Iterator<Item = Entity> + '_>;
+
+    fn count_matching(&self, filter: &ComponentFilter) -> usize {
+        self.matching_entities(filter).count()
+    }
+
+    fn any_matching(&self, filter: &ComponentFilter) -> bool {
+        self.matching_entities(filter).next().is_some()
+    }
+
+    // Number of enabled (entity, component) pairs, markers included.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn column(&self, component: ComponentId) -> Option<&dyn ComponentColumn>;
+
+    fn column_mut(&mut self, component: ComponentId) -> Option<&mut dyn ComponentColumn>;
+
+    fn column_or_insert_with(
+        &mut self,
+        component: ComponentId,
+        new_column: &dyn Fn() -> Box<dyn ComponentColumn>,
+    ) -> &mut dyn ComponentColumn;
+
+    fn columns(&self) -> Box<dyn Iterator<Item = &dyn ComponentColumn> + '_>;
+}
@@ -0,0 +1,117 @@
+use super::{column::ColumnMap, Column, ComponentColumn, ComponentFilter, ComponentStorage};
+use crate::{ComponentId, Entity};
+
+// Every component keeps its own sparse set of entities. Membership checks are a couple of array
+// lookups, and queries walk the smallest required set while probing the others.
+#[derive(Default)]
+pub struct SparseSetStorage {
+    // Indexed by component id
+    entity_sets: Vec<Column<()>>,
+    columns: ColumnMap,
+    len: usize,
+}
+
+impl SparseSetStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ComponentStorage for SparseSetStorage {
+    fn enable(&mut self, entity: Entity, component: ComponentId) -> bool {
+        if component.0 >= self.entity_sets.len() {
+            self.entity_sets.resize_with(component.0 + 1, Column::new);
+        }
+        let was_enabled = self.entity_sets[component.0]
+            .insert(entity, ())
+            .is_some();
+        if !was_enabled {
+            self.len += 1;
+        }
+        was_enabled
+    }
+
+    fn disable(&mut self, entity: Entity, component: ComponentId) -> bool {
+        let was_enabled = self
+            .entity_sets
+            .get_mut(component.0)
+            .and_then(|entities| entities.remove(entity.0))
+            .is_some();
+        if was_enabled {
+            self.len -= 1;
+        }
+        was_enabled
+    }
+
+    fn disable_all(&mut self, component: ComponentId) -> Vec<Entity> {
+        let Some(entities) = self.entity_sets.get_mut(component.0) else {
+            return Vec::new();
+        };
+        let entities: Vec<Entity> = std::mem::take(entities).entities().collect();
+        self.len -= entities.len();
+        entities
+    }
+
+    fn contains(&self, entity: Entity, component: ComponentId) -> bool {
+        self.entity_sets
+            .get(component.0)
+            .is_some_and(|entities| entities.contains_id(entity.0))
+    }
+
+    fn components_of(&self, entity: Entity) -> Vec<ComponentId> {
+        self.entity_sets
+            .iter()
+            .enumerate()
+            .filter(|(_, entities)| entities.contains_id(entity.0))
+            .map(|(index, _)| ComponentId(index))
+            .collect()
+    }
+
+    fn matching_entities(&self, filter: &ComponentFilter) -> Box<dyn Iterator<Item = Entity> + '_> {
+        let mut required = Vec::new();
+        for component in filter.required.iter() {
+            match self.entity_sets.get(component.0) {
+                Some(entities) => required.push(entities),
+                // Nobody has this component
+                None => return Box::new(std::iter::empty()),
+            }
+        }
+        let Some(smallest) = required.iter().copied().min_by_key(|entities| entities.len()) else {
+            return Box::new(std::iter::empty());
+        };
+        let excluded: Vec<&Column<()>> = filter
+            .excluded
+            .iter()
+            .filter_map(|component| self.entity_sets.get(component.0))
+            .collect();
+
+        Box::new(smallest.entities().filter(move |entity| {
+            required.iter().all(|entities| entities.contains_id(entity.0))
+                && !excluded.iter().any(|entities| entities.contains_id(entity.0))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn column(&self, component: ComponentId) -> Option<&dyn ComponentColumn> {
+        self.columns.get(component)
+    }
+
+    fn column_mut(&mut self, component: ComponentId) -> Option<&mut dyn ComponentColumn> {
+        self.columns.get_mut(component)
+    }
+
+    fn column_or_insert_with(
+        &mut self,
+        component: ComponentId,
+        new_column: &dyn Fn() -> Box<dyn ComponentColumn>,
+    ) -> &mut dyn ComponentColumn {
+        self.columns.get_or_insert_with(component, new_column)
+    }
+
+    fn columns(&self) -> Box<dyn Iterator<Item = &dyn ComponentColumn> + '_> {
+        Box::new(self.columns.iter())
+    }
+}
@@ -0,0 +1,142 @@
+use super::{column::ColumnMap, ComponentColumn, ComponentFilter, ComponentStorage};
+use crate::{
+    table::{NodeId, Table},
+    utils::bitset::{BitSet, Intersection},
+    ComponentId, Entity,
+};
+
+// The table: every enabled component is a node on the entity's row and the component's column.
+// Queries are answered from a bitset of entities per component, by intersecting the bitsets of
+// the filtered components.
+#[derive(Default)]
+pub struct TableStorage {
+    table: Table,
+    // Indexed by component id
+    entity_bits: Vec<BitSet>,
+    // The generation of every entity id the table has seen, as the bitsets only hold ids
+    generations: Vec<u32>,
+    columns: ColumnMap,
+}
+
+impl TableStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    // ANDs the entity bitsets of the required components and ANDNOTs those of the excluded ones.
+    fn intersection(&self, filter: &ComponentFilter) -> Intersection<'_> {
+        let mut required = Vec::new();
+        for component in filter.required.iter() {
+            match self.entity_bits.get(component.0) {
+                Some(entities) => required.push(entities),
+                // Nobody has this component
+                None => return Intersection::new(Vec::new(), Vec::new()),
+            }
+        }
+        let excluded = filter
+            .excluded
+            .iter()
+            .filter_map(|component| self.entity_bits.get(component.0))
+            .collect();
+
+        Intersection::new(required, excluded)
+    }
+
+    fn entity(&self, id: usize) -> Entity {
+        Entity(id, self.generations[id])
+    }
+}
+
+impl ComponentStorage for TableStorage {
+    fn enable(&mut self, entity: Entity, component: ComponentId) -> bool {
+        let was_enabled = self.contains(entity, component);
+        // Enabling only fails on a corrupted table, which would also break every other lookup.
+        self.table
+            .enable_node([entity.0, component.0])
+            .expect("component table is corrupted");
+        if component.0 >= self.entity_bits.len() {
+            self.entity_bits.resize_with(component.0 + 1, BitSet::new);
+        }
+        self.entity_bits[component.0].insert(entity.0);
+        if entity.0 >= self.generations.len() {
+            self.generations.resize(entity.0 + 1, 0);
+        }
+        self.generations[entity.0] = entity.1;
+        was_enabled
+    }
+
+    fn disable(&mut self, entity: Entity, component: ComponentId) -> bool {
+        let node_id = NodeId([entity.0, component.0]);
+        let was_enabled = self.table.contains_node(&node_id);
+        if was_enabled {
+            let _ = self.table.disable_node(&node_id);
+            self.entity_bits[component.0].remove(entity.0);
+        }
+        was_enabled
+    }
+
+    fn disable_all(&mut self, component: ComponentId) -> Vec<Entity> {
+        let Some(entities) = self.entity_bits.get_mut(component.0) else {
+            return Vec::new();
+        };
+        entities.clear();
+        self.table
+            .disable_line(1, component.0)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| self.entity(id))
+            .collect()
+    }
+
+    fn contains(&self, entity: Entity, component: ComponentId) -> bool {
+        self.table
+            .contains_node(&NodeId([entity.0, component.0]))
+    }
+
+    fn components_of(&self, entity: Entity) -> Vec<ComponentId> {
+        self.table
+            .line_positions(0, entity.0)
+            .map(ComponentId)
+            .collect()
+    }
+
+    fn matching_entities(&self, filter: &ComponentFilter) -> Box<dyn Iterator<Item = Entity> + '_> {
+        Box::new(self.intersection(filter).map(|id| self.entity(id)))
+    }
+
+    fn count_matching(&self, filter: &ComponentFilter) -> usize {
+        self.intersection(filter).remaining()
+    }
+
+    fn any_matching(&self, filter: &ComponentFilter) -> bool {
+        !self.intersection(filter).is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.table.size()
+    }
+
+    fn column(&self, component: ComponentId) -> Option<&dyn ComponentColumn> {
+        self.columns.get(component)
+    }
+
+    fn column_mut(&mut self, component: ComponentId) -> Option<&mut dyn ComponentColumn> {
+        self.columns.get_mut(component)
+    }
+
+    fn column_or_insert_with(
+        &mut self,
+        component: ComponentId,
+        new_column: &dyn Fn() -> Box<dyn ComponentColumn>,
+    ) -> &mut dyn ComponentColumn {
+        self.columns.get_or_insert_with(component, new_column)
+    }
+
+    fn columns(&self) -> Box<dyn Iterator<Item = &dyn ComponentColumn> + '_> {
+        Box::new(self.columns.iter())
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    alloc::{self, Layout},
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    slice,
+};
+
+// An owned byte buffer allocated with a given layout, so its bytes can be read as the type the
+// layout describes.
+pub struct AlignedBytes {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+impl AlignedBytes {
+    // Panics if `bytes` doesn't have the layout's size.
+    pub fn copy_from(layout: Layout, bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), layout.size(), "bytes don't match the layout's size");
+        let ptr = if layout.size() == 0 {
+            // Nothing is allocated, a pointer with the right alignment is enough.
+            NonNull::new(layout.align() as *mut u8).unwrap()
+        } else {
+            // SAFETY: the layout has a nonzero size.
+            let ptr = unsafe { alloc::alloc(layout) };
+            NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        // SAFETY: the allocation is valid for `layout.size()` bytes and can't overlap `bytes`.
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.as_ptr(), bytes.len()) };
+        Self { ptr, layout }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the buffer holds `layout.size()` initialized bytes.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: see `deref`, and the buffer is borrowed mutably.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Clone for AlignedBytes {
+    fn clone(&self) -> Self {
+        Self::copy_from(self.layout, self)
+    }
+}
+
+impl Debug for AlignedBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+impl Drop for AlignedBytes {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            // SAFETY: the buffer was allocated with this layout in `copy_from`.
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+        }
+    }
+}
+
+// SAFETY: the buffer is owned and only reached through `&self` or `&mut self`, like a `Box<[u8]>`.
+unsafe impl Send for AlignedBytes {}
+unsafe impl Sync for AlignedBytes {}
@@ -0,0 +1,145 @@
+const WORD_BITS: usize = u64::BITS as usize;
+
+// A growable set of small integers, one bit each.
+#[derive(Debug, Default, Clone)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(bits: usize) -> Self {
+        Self {
+            words: vec![0; bits.div_ceil(WORD_BITS)],
+        }
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        let word = index / WORD_BITS;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (index % WORD_BITS);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Some(word) = self.words.get_mut(index / WORD_BITS) {
+            *word &= !(1 << (index % WORD_BITS));
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.words
+            .get(index / WORD_BITS)
+            .is_some_and(|word| word & (1 << (index % WORD_BITS)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    pub fn iter(&self) -> Intersection<'_> {
+        Intersection::new(vec![self], Vec::new())
+    }
+
+    // Empties the set but keeps its allocation.
+    pub fn clear(&mut self) {
+        self.words.fill(0);
+    }
+}
+
+// Lazily yields the bits set in every `required` set and in none of the `excluded` ones,
+// combining the sets one word at a time.
+pub struct Intersection<'a> {
+    required: Vec<&'a BitSet>,
+    excluded: Vec<&'a BitSet>,
+    word_count: usize,
+    word_index: usize,
+    word: u64,
+}
+
+impl<'a> Intersection<'a> {
+    // Without any required set nothing is yielded.
+    pub fn new(required: Vec<&'a BitSet>, excluded: Vec<&'a BitSet>) -> Self {
+        let word_count = required
+            .iter()
+            .map(|set| set.words.len())
+            .min()
+            .unwrap_or(0);
+
+        Self {
+            required,
+            excluded,
+            word_count,
+            word_index: 0,
+            word: 0,
+        }
+    }
+
+    // Number of bits left to yield, counted a word at a time.
+    pub fn remaining(&self) -> usize {
+        self.word.count_ones() as usize
+            + (self.word_index..self.word_count)
+                .map(|word_index| self.combined_word(word_index).count_ones() as usize)
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.word == 0
+            && (self.word_index..self.word_count)
+                .all(|word_index| self.combined_word(word_index) == 0)
+    }
+
+    fn combined_word(&self, word_index: usize) -> u64 {
+        let required = self
+            .required
+            .iter()
+            .fold(u64::MAX, |word, set| word & set.words[word_index]);
+        self.excluded.iter().fold(required, |word, set| {
+            word & !set.words.get(word_index).copied().unwrap_or(0)
+        })
+    }
+}
+
+impl Iterator for Intersection<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            if self.word_index >= self.word_count {
+                return None;
+            }
+            self.word = self.combined_word(self.word_index);
+            self.word_index += 1;
+        }
+
+        let bit = self.word.trailing_zeros() as usize;
+        // Clear the lowest set bit
+        self.word &= self.word - 1;
+        Some((self.word_index - 1) * WORD_BITS + bit)
+    }
+}
+
+// Two sets are equal when they hold the same bits, however many words they have allocated.
+impl PartialEq for BitSet {
+    fn eq(&self, other: &Self) -> bool {
+        let (shorter, longer) = if self.words.len() <= other.words.len() {
+            (&self.words, &other.words)
+        } else {
+            (&other.words, &self.words)
+        };
+        shorter == &longer[..shorter.len()] && longer[shorter.len()..].iter().all(|word| *word == 0)
+    }
+}
+
+impl Eq for BitSet {}
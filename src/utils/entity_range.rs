@@ -1,3 +1,5 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
 #[derive(Debug, Clone)]
 pub(crate) struct ValidEntityRange {
     pub lower_bound: usize,
@@ -12,16 +14,24 @@ impl ValidEntityRange {
         }
     }
 
-    pub(crate) fn split_at(&mut self, index: &usize) -> Option<ValidEntityRange> {
-        if *index >= self.lower_bound {
-            let new_range = match *index == self.lower_bound {
+    // `None` for the unbounded range
+    pub(crate) fn len(&self) -> Option<usize> {
+        self.upper_bound
+            .map(|upper_bound| upper_bound + 1 - self.lower_bound)
+    }
+
+    // Cuts `lower..=upper` out of the range, keeping what follows it and returning what precedes
+    // it, if anything does.
+    pub(crate) fn split_around(&mut self, lower: &usize, upper: &usize) -> Option<ValidEntityRange> {
+        if *lower >= self.lower_bound {
+            let new_range = match *lower == self.lower_bound {
                 false => Some(ValidEntityRange {
                     lower_bound: self.lower_bound,
-                    upper_bound: Some(index - 1),
+                    upper_bound: Some(lower - 1),
                 }),
                 true => None,
             };
-            self.lower_bound = index + 1;
+            self.lower_bound = upper + 1;
             new_range
         } else {
             None
@@ -96,3 +106,133 @@ impl ValidEntityRange {
         }
     }
 }
+
+// Disjoint entity id ranges, ordered by lower bound and merged whenever they touch. Looking up,
+// adding or removing ids only visits the neighboring ranges, so it takes O(log n) in the number
+// of ranges.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct EntityRanges {
+    ranges: BTreeMap<usize, ValidEntityRange>,
+    // The number of ids before each range and its lower bound, in order, so `nth` can binary
+    // search them. Built by the first `nth` after the ranges change.
+    offsets: OnceLock<Vec<(usize, usize)>>,
+}
+
+impl EntityRanges {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // Number of separate ranges
+    pub(crate) fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &ValidEntityRange> {
+        self.ranges.values()
+    }
+
+    pub(crate) fn first(&self) -> Option<usize> {
+        self.ranges.keys().next().copied()
+    }
+
+    pub(crate) fn range_containing(&self, index: &usize) -> Option<&ValidEntityRange> {
+        self.ranges
+            .range(..=index)
+            .next_back()
+            .map(|(_, range)| range)
+            .filter(|range| range.contains(index))
+    }
+
+    pub(crate) fn contains(&self, index: &usize) -> bool {
+        self.range_containing(index).is_some()
+    }
+
+    // Adds ids that aren't in any range yet, merging them with the ranges they touch.
+    pub(crate) fn insert(&mut self, mut range_glob: ValidEntityRange) {
+        self.offsets.take();
+        let previous_range = self
+            .ranges
+            .range(..range_glob.lower_bound)
+            .next_back()
+            .map(|(_, range)| range.clone());
+        if let Some(previous_range) = previous_range {
+            if range_glob.merge_with(&previous_range) {
+                self.ranges.remove(&previous_range.lower_bound);
+            }
+        }
+
+        // Every following range starting at most one past the new ids touches them
+        loop {
+            let next_range = match range_glob.upper_bound {
+                Some(upper_bound) => self
+                    .ranges
+                    .range(range_glob.lower_bound..=upper_bound.saturating_add(1))
+                    .next(),
+                None => self.ranges.range(range_glob.lower_bound..).next(),
+            };
+            let Some(next_range) = next_range.map(|(_, range)| range.clone()) else {
+                break;
+            };
+            range_glob.merge_with(&next_range);
+            self.ranges.remove(&next_range.lower_bound);
+        }
+
+        self.ranges.insert(range_glob.lower_bound, range_glob);
+    }
+
+    // Removes `lower..=upper`, returning whether those ids were all in one range.
+    pub(crate) fn remove(&mut self, lower: usize, upper: usize) -> bool {
+        let Some(mut old_range) = self
+            .range_containing(&lower)
+            .filter(|range| range.contains(&upper))
+            .cloned()
+        else {
+            return false;
+        };
+
+        self.offsets.take();
+        self.ranges.remove(&old_range.lower_bound);
+        if let Some(new_range) = old_range.split_around(&lower, &upper) {
+            self.ranges.insert(new_range.lower_bound, new_range);
+        }
+        if old_range.is_valid() {
+            self.ranges.insert(old_range.lower_bound, old_range);
+        }
+        true
+    }
+
+    // Removes `count` consecutive ids from the first range that can hold them and returns the
+    // first one.
+    pub(crate) fn take_first_fit(&mut self, count: usize) -> Option<usize> {
+        let first_id = self
+            .iter()
+            .find(|range| range.len().is_none_or(|len| len >= count))?
+            .lower_bound;
+        self.remove(first_id, first_id + count - 1);
+        Some(first_id)
+    }
+
+    // The id `skipped` places after the first one, counting only ids in the ranges. Takes
+    // O(log n) in the number of ranges, once the offsets are built.
+    pub(crate) fn nth(&self, skipped: usize) -> Option<usize> {
+        let offsets = self.offsets.get_or_init(|| {
+            let mut before = 0;
+            self.iter()
+                .map(|range| {
+                    let offset = (before, range.lower_bound);
+                    before += range.len().unwrap_or(0);
+                    offset
+                })
+                .collect()
+        });
+        let index = offsets
+            .partition_point(|&(before, _)| before <= skipped)
+            .checked_sub(1)?;
+        let (before, lower_bound) = offsets[index];
+        self.ranges[&lower_bound]
+            .len()
+            .is_none_or(|len| skipped - before < len)
+            .then_some(lower_bound + skipped - before)
+    }
+}
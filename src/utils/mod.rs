@@ -1 +1,3 @@
+pub(crate) mod aligned_bytes;
+pub(crate) mod bitset;
 pub(crate) mod entity_range;
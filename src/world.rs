@@ -1,107 +1,627 @@
-use std::{any::type_name, fmt::Debug, slice::Iter};
+use std::{
+    alloc::Layout,
+    borrow::Cow,
+    ptr::NonNull,
+    fmt::{Debug, Display},
+    ops::Range,
+    slice::Iter,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use crate::{
-    events::ECSEvent, hashset, table::{NodeBundle, NodeFilter, NodeId, Table}, utils::entity_range::ValidEntityRange, Children, Component, Entity, Parent
+    bundle::Bundle, component::is_marker, events::ECSEvent, query::{Query, QueryData, ReadOnlyQueryData}, registry::{
+        ComponentHook, ComponentHooks, ComponentInfo, ComponentRegistry, ExclusivityPolicy,
+    }, storage::{Column, ComponentFilter, ComponentStorage, TableStorage}, table::{NodeBundle, NodeId}, utils::{aligned_bytes::AlignedBytes, bitset::BitSet, entity_range::{EntityRanges, ValidEntityRange}}, Children, Component, ComponentId, DynamicComponent, Entity, EntityIdStats, MutableComponent, MAX_ENTITY_ID, Parent
 };
-use hashbrown::{HashMap, HashSet};
+use hashbrown::HashSet;
 
-#[derive(Default)]
 pub struct World {
-    valid_entities: Vec<ValidEntityRange>,
-    node_table: Table,
-    node_data: HashMap<NodeId, Box<(dyn Component + 'static)>>,
+    valid_entities: EntityRanges,
+    // The generation of every entity id that was ever despawned, indexed by id
+    generations: Vec<u32>,
+    // The ids of the spawned entities, whether they have components or not
+    live_entities: BitSet,
+    entity_count: usize,
+    // Ids set aside by `reserve_range`, which are only spawned through `spawn_at` and go back here
+    // instead of to `valid_entities` once despawned
+    reserved_ranges: EntityRanges,
+    // Number of free ids handed out by `reserve_entity` since the last flush. They are the first
+    // ones of `valid_entities`, in order.
+    reserved_entities: AtomicUsize,
+    // The entities being despawned and the components whose `on_remove` hook is running,
+    // innermost last, so hooks that remove their own component or entity don't recurse
+    despawning: Vec<usize>,
+    removing: Vec<(usize, ComponentId)>,
+    storage: Box<dyn ComponentStorage>,
     ecs_events: Vec<ECSEvent>,
-    reverse_type_lookup: HashMap<usize, &'static str>,
+    registry: ComponentRegistry,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            valid_entities: EntityRanges::new(),
+            generations: Vec::new(),
+            live_entities: BitSet::new(),
+            entity_count: 0,
+            reserved_entities: AtomicUsize::new(0),
+            reserved_ranges: EntityRanges::new(),
+            despawning: Vec::new(),
+            removing: Vec::new(),
+            storage: Box::new(TableStorage::new()),
+            ecs_events: Vec::new(),
+            registry: ComponentRegistry::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    ComponentHashCollision {
+        hash: usize,
+        registered: String,
+        rejected: String,
+    },
+    UnregisteredComponent(ComponentId),
+    NotDynamicComponent(ComponentId),
+    DynamicComponentSizeMismatch {
+        component: ComponentId,
+        expected: usize,
+        found: usize,
+    },
+    ExclusiveComponentConflict {
+        entity: Entity,
+        component: ComponentId,
+        conflicting: ComponentId,
+    },
+    EntityNotAlive(Entity),
+    EntityIdTaken(usize),
+    StaleEntity(Entity),
+    EntityIdOutOfRange(usize),
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ComponentHashCollision {
+                hash,
+                registered,
+                rejected,
+            } => f.write_fmt(format_args!(
+                "component `{}` has the same hash ({:#x}) as the already registered component `{}`",
+                rejected, hash, registered
+            )),
+            Self::UnregisteredComponent(component) => f.write_fmt(format_args!(
+                "no component is registered with id {}",
+                component.index()
+            )),
+            Self::NotDynamicComponent(component) => f.write_fmt(format_args!(
+                "the component with id {} is not a dynamic component",
+                component.index()
+            )),
+            Self::DynamicComponentSizeMismatch {
+                component,
+                expected,
+                found,
+            } => f.write_fmt(format_args!(
+                "dynamic component {} expects {} bytes of data, got {}",
+                component.index(),
+                expected,
+                found
+            )),
+            Self::ExclusiveComponentConflict {
+                entity,
+                component,
+                conflicting,
+            } => f.write_fmt(format_args!(
+                "component {} can't be enabled for {:?}, which has the mutually exclusive component {}",
+                component.index(),
+                entity,
+                conflicting.index()
+            )),
+            Self::EntityNotAlive(entity) => {
+                f.write_fmt(format_args!("{:?} is not alive", entity))
+            }
+            Self::EntityIdTaken(id) => {
+                f.write_fmt(format_args!("entity id {:#x} is already taken", id))
+            }
+            Self::StaleEntity(entity) => f.write_fmt(format_args!(
+                "{:?} is older than the current generation of its id",
+                entity
+            )),
+            Self::EntityIdOutOfRange(id) => f.write_fmt(format_args!(
+                "entity id {:#x} is above the largest entity id {:#x}",
+                id, MAX_ENTITY_ID
+            )),
+        }
+    }
 }
 
+impl std::error::Error for WorldError {}
+
 impl World {
     pub fn new() -> Self {
-        let mut new_world = Self::default();
+        Self::with_storage(TableStorage::new())
+    }
+
+    pub fn with_storage(storage: impl ComponentStorage + 'static) -> Self {
+        let mut new_world = Self {
+            storage: Box::new(storage),
+            ..Default::default()
+        };
         new_world
             .valid_entities
-            .push(ValidEntityRange::new(0, None));
+            .insert(ValidEntityRange::new(0, None));
         new_world
     }
 
-    pub fn enable_component_for_entity<T>(&mut self, entity: Entity, component: T)
+    pub fn storage(&self) -> &dyn ComponentStorage {
+        self.storage.as_ref()
+    }
+
+    pub fn register_component<T>(&mut self) -> Result<ComponentId, WorldError>
     where
         T: Component + 'static,
     {
-        let component_hash = T::hash();
-        self.reverse_type_lookup
-            .insert(component_hash, type_name::<T>());
+        self.registry.register::<T>()
+    }
 
-        let new_node_position = [entity.0, component_hash];
-        if let Ok(enabled_node_id) = self.node_table.enable_node(new_node_position) {
-            if let Some(old_data) = self.node_data.insert(enabled_node_id, Box::new(component)) {
-                self.ecs_events
-                    .push(ECSEvent::ComponentChanged(entity, old_data));
+    pub fn register_component_info(
+        &mut self,
+        info: ComponentInfo,
+    ) -> Result<ComponentId, WorldError> {
+        self.registry.register_info(info)
+    }
+
+    pub fn register_dynamic_component(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        layout: Layout,
+    ) -> Result<ComponentId, WorldError> {
+        self.registry.register_dynamic(name, layout)
+    }
+
+    pub fn component_hooks_mut(&mut self, component: ComponentId) -> Option<&mut ComponentHooks> {
+        self.registry
+            .get_mut(component)
+            .map(|info| info.hooks_mut())
+    }
+
+    pub fn set_exclusive_group(
+        &mut self,
+        group: impl Into<Cow<'static, str>>,
+        members: &[ComponentId],
+        policy: ExclusivityPolicy,
+    ) -> Result<(), WorldError> {
+        self.registry.set_exclusive_group(group, members, policy)
+    }
+
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    pub fn enable_component_for_entity<T>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<(), WorldError>
+    where
+        T: Component + 'static,
+    {
+        self.flush();
+        self.check_alive(entity)?;
+        let component_id = self.register_component::<T>()?;
+        self.insert_component_with(entity, component_id, |world, was_enabled| {
+            if is_marker::<T>() {
+                // A replaced marker is indistinguishable from the new one, so the incoming
+                // value (a zero-sized box, no allocation) stands in for it.
+                was_enabled.then(|| Box::new(component) as Box<dyn Component>)
             } else {
-                self.ecs_events
-                    .push(ECSEvent::ComponentAdded(entity, component_hash));
+                world
+                    .column_or_insert::<T>(component_id)
+                    .insert(entity, component)
+                    .map(|old| Box::new(old) as Box<dyn Component>)
             }
-        }
+        })
     }
 
     pub fn disable_component_for_entity<T>(&mut self, entity: Entity)
     where
         T: Component + 'static,
     {
-        let node_id_to_remove = NodeId([entity.0, T::hash()]);
-        if let Ok(disabled_node_id) = self.node_table.disable_node(&node_id_to_remove) {
-            if let Some(old_data) = self.node_data.remove(&disabled_node_id) {
-                self.ecs_events
-                    .push(ECSEvent::ComponentRemoved(entity, old_data));
+        let Some(component) = self.component_id::<T>() else {
+            return;
+        };
+        if !self.is_alive(entity) {
+            return;
+        }
+
+        self.remove_component_data(entity, component);
+    }
+
+    pub fn enable_dynamic_component_for_entity(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), WorldError> {
+        self.flush();
+        self.check_alive(entity)?;
+        let info = self.dynamic_component_info(component)?;
+        let data = data.as_ref();
+        if data.len() != info.size() {
+            return Err(WorldError::DynamicComponentSizeMismatch {
+                component,
+                expected: info.size(),
+                found: data.len(),
+            });
+        }
+
+        let value = DynamicComponent {
+            hash: info.hash(),
+            data: AlignedBytes::copy_from(info.layout(), data),
+        };
+        self.insert_component_data(entity, component, Box::new(value))
+    }
+
+    pub fn disable_dynamic_component_for_entity(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Result<(), WorldError> {
+        self.check_alive(entity)?;
+        self.dynamic_component_info(component)?;
+        self.remove_component_data(entity, component);
+        Ok(())
+    }
+
+    // Removes `T` from every entity that has it in one pass over the storage, returning how many
+    // had it. The values are dropped, or handed out in `ComponentRemoved` events if `emit_events`
+    // is set.
+    pub fn clear_component<T>(&mut self, emit_events: bool) -> usize
+    where
+        T: Component + 'static,
+    {
+        self.clear_component_filtered::<T>(&[], &[], emit_events)
+    }
+
+    // Removes `T` from the entities that have it, have every component of `with` and none of
+    // `without`.
+    pub fn clear_component_filtered<T>(
+        &mut self,
+        with: &[ComponentId],
+        without: &[ComponentId],
+        emit_events: bool,
+    ) -> usize
+    where
+        T: Component + 'static,
+    {
+        let Some(component) = self.component_id::<T>() else {
+            return 0;
+        };
+        let filtered = !with.is_empty() || !without.is_empty();
+        let filter = ComponentFilter {
+            required: std::iter::once(component).chain(with.iter().copied()).collect(),
+            excluded: without.to_vec(),
+        };
+
+        // Removal hooks still see every entity before its component is gone
+        if self
+            .registry
+            .get(component)
+            .is_some_and(|info| info.hooks().on_remove.is_some())
+        {
+            let entities: Vec<Entity> = self.storage.matching_entities(&filter).collect();
+            for entity in entities {
+                // An earlier hook may already have removed this component
+                if self.storage.contains(entity, component) {
+                    self.run_remove_hook(component, entity);
+                }
+            }
+        }
+
+        let entities = match filtered {
+            true => self.storage.disable_matching(component, &filter),
+            false => self.storage.disable_all(component),
+        };
+
+        if emit_events {
+            for entity in entities.iter() {
+                let old_data = match is_marker::<T>() {
+                    true => self.registry.get(component).and_then(|info| info.marker()),
+                    false => self
+                        .storage
+                        .column_mut(component)
+                        .and_then(|column| column.remove_boxed(entity.0)),
+                };
+                if let Some(old_data) = old_data {
+                    self.ecs_events
+                        .push(ECSEvent::ComponentRemoved(*entity, old_data));
+                }
+            }
+        } else if let Some(column) = self.storage.column_mut(component) {
+            match filtered {
+                true => column.remove_entities(&entities),
+                false => column.clear(),
             }
         }
+
+        entities.len()
     }
 
     pub fn alloc_entity(&mut self) -> Entity {
-        let entity_id = self.first_valid_entity().unwrap();
-        self.remove_valid_entity(entity_id);
-        let new_entity = Entity(entity_id);
-        self.ecs_events.push(ECSEvent::EntitySpawned(new_entity));
-        new_entity
+        self.flush();
+        self.spawn_first_valid_entity()
+    }
+
+    // Spawns an entity per bundle. Their ids are taken in one step from the first free range long
+    // enough to hold all of them, so the returned entities are consecutive. Values are stored one
+    // component at a time, then required components are added and `on_add` hooks run. If the
+    // batch fails, none of its entities are left spawned.
+    pub fn spawn_batch<B>(
+        &mut self,
+        bundles: impl IntoIterator<Item = B>,
+    ) -> Result<Vec<Entity>, WorldError>
+    where
+        B: Bundle,
+    {
+        let bundles: Vec<B> = bundles.into_iter().collect();
+        if bundles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = bundles.len();
+        self.flush();
+        let components = B::register(self)?;
+        B::reserve(self, count);
+
+        let first_id = self.take_entity_range(count);
+        let entities: Vec<Entity> = (first_id..first_id + count)
+            .map(|id| Entity(id, self.generation_of(id)))
+            .collect();
+
+        if !self.are_independent(&components) {
+            // Members of one exclusive group displace each other, so every entity gets its
+            // components one by one.
+            for (index, bundle) in bundles.into_iter().enumerate() {
+                let entity = entities[index];
+                self.mark_alive(entity.0);
+                self.ecs_events.push(ECSEvent::EntitySpawned(entity));
+                if let Err(error) = bundle.enable_for_entity(self, entity) {
+                    // A hook may already have despawned some of them
+                    for spawned in entities[..=index].iter() {
+                        let _ = self.dealloc_entity(*spawned);
+                    }
+                    if index + 1 < count {
+                        self.valid_entities.insert(ValidEntityRange::new(
+                            first_id + index + 1,
+                            Some(first_id + count - 1),
+                        ));
+                    }
+                    return Err(error);
+                }
+            }
+            return Ok(entities);
+        }
+
+        for entity in entities.iter() {
+            self.mark_alive(entity.0);
+        }
+        B::insert_batch(bundles, self, &entities, &components);
+        for entity in entities.iter() {
+            self.ecs_events.push(ECSEvent::EntitySpawned(*entity));
+            self.ecs_events.extend(
+                components
+                    .iter()
+                    .map(|component| ECSEvent::ComponentAdded(*entity, *component)),
+            );
+        }
+
+        let with_follow_ups: Vec<ComponentId> = components
+            .iter()
+            .copied()
+            .filter(|component| {
+                !self.registry.requirements_of(*component).is_empty()
+                    || self
+                        .registry
+                        .get(*component)
+                        .is_some_and(|info| info.hooks().on_add.is_some())
+            })
+            .collect();
+        if !with_follow_ups.is_empty() {
+            for entity in entities.iter() {
+                for component in with_follow_ups.iter() {
+                    // An earlier hook may have despawned the entity or removed the component
+                    if !self.is_alive(*entity) || !self.storage.contains(*entity, *component) {
+                        continue;
+                    }
+                    self.insert_required_components(*entity, *component);
+                    self.run_hook(*component, *entity, |hooks| hooks.on_add);
+                }
+            }
+        }
+        Ok(entities)
     }
 
-    pub fn dealloc_entity(&mut self, entity: Entity) {
-        // deallocate any children
+    // Fails if `entity` was never spawned or was already despawned.
+    pub fn dealloc_entity(&mut self, entity: Entity) -> Result<(), WorldError> {
+        self.flush();
+        self.check_alive(entity)?;
+        // A removal hook despawning its own entity finds it already on its way out
+        if self.despawning.contains(&entity.0) {
+            return Ok(());
+        }
+        self.despawning.push(entity.0);
+
+        // deallocate any children, unless they were despawned on their own
         if let Some(children) = self.entity_component::<Children>(entity).cloned() {
             for child in children.children.iter() {
-                self.dealloc_entity(*child);
+                let _ = self.dealloc_entity(*child);
             }
         }
         
         // delete data for this entity
-        if let Ok(component_vector) = self.node_table.get_dimension_at_indices(
-            0,
-            NodeFilter {
-                get: hashset!(entity.0),
-                ..Default::default()
-            },
-        ) {
-            for bundle in component_vector.iter() {
-                for node_id in bundle.nodes.iter() {
-                    if let Ok(node_id) = self.node_table.disable_node(node_id) {
-                        self.node_data.remove(&node_id);
-                    }
-                }
+        for component in self.storage.components_of(entity) {
+            // An earlier hook may already have removed this component
+            if self.storage.contains(entity, component) {
+                self.run_remove_hook(component, entity);
+            }
+            self.storage.disable(entity, component);
+            if let Some(column) = self.storage.column_mut(component) {
+                column.remove_boxed(entity.0);
             }
+        }
+        // Handles to this entity are stale from now on
+        if entity.0 >= self.generations.len() {
+            self.generations.resize(entity.0 + 1, 0);
+        }
+        self.generations[entity.0] = entity.1.wrapping_add(1);
+        self.live_entities.remove(entity.0);
+        self.entity_count -= 1;
+        if !self.is_reserved_id(entity.0) {
             self.add_valid_entity(entity.0);
-            self.ecs_events.push(ECSEvent::EntityDespawned(entity));
         }
+        self.ecs_events.push(ECSEvent::EntityDespawned(entity));
+        self.despawning.pop();
+        Ok(())
+    }
+
+    // Hands out the id of an entity that is spawned at the next flush, without needing the world
+    // mutably, e.g. while it is being queried. Reserved ids are the next ones `alloc_entity`
+    // would have returned, so they are never handed out twice.
+    pub fn reserve_entity(&self) -> Entity {
+        let skipped = self.reserved_entities.fetch_add(1, Ordering::Relaxed);
+        let id = self
+            .valid_entities
+            .nth(skipped)
+            .expect("the last entity range is unbounded");
+        Entity(id, self.generation_of(id))
+    }
+
+    // Spawns `entity` with its exact id and generation, e.g. to mirror another world. Its id has
+    // to be free or in a range set aside by `reserve_range`, and its generation can't be older
+    // than the id's, or handles to despawned entities would come back to life. Ids above
+    // `MAX_ENTITY_ID` are rejected.
+    pub fn spawn_at(&mut self, entity: Entity) -> Result<Entity, WorldError> {
+        self.flush();
+        if entity.0 > MAX_ENTITY_ID {
+            return Err(WorldError::EntityIdOutOfRange(entity.0));
+        }
+        let is_free = self.valid_entities.contains(&entity.0);
+        if !is_free && (!self.is_reserved_id(entity.0) || self.live_entities.contains(entity.0)) {
+            return Err(WorldError::EntityIdTaken(entity.0));
+        }
+        if entity.1 < self.generation_of(entity.0) {
+            return Err(WorldError::StaleEntity(entity));
+        }
+        if is_free {
+            self.remove_valid_entity(entity.0);
+        }
+
+        if entity.0 >= self.generations.len() {
+            self.generations.resize(entity.0 + 1, 0);
+        }
+        self.generations[entity.0] = entity.1;
+        self.mark_alive(entity.0);
+        self.ecs_events.push(ECSEvent::EntitySpawned(entity));
+        Ok(entity)
+    }
+
+    // Sets aside `count` consecutive free ids that `alloc_entity` won't hand out, to be spawned
+    // with `spawn_at`.
+    pub fn reserve_range(&mut self, count: usize) -> Range<usize> {
+        self.flush();
+        if count == 0 {
+            return 0..0;
+        }
+        let first_id = self.take_entity_range(count);
+        self.reserved_ranges
+            .insert(ValidEntityRange::new(first_id, Some(first_id + count - 1)));
+        first_id..first_id + count
+    }
+
+    // Sets aside the given ids, which all have to be free.
+    pub fn reserve_range_at(&mut self, ids: Range<usize>) -> Result<(), WorldError> {
+        self.flush();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let (lower, upper) = (ids.start, ids.end - 1);
+        if upper > MAX_ENTITY_ID {
+            return Err(WorldError::EntityIdOutOfRange(upper));
+        }
+        // Free ranges are merged, so free ids in a row are all in one range
+        match self.valid_entities.range_containing(&lower) {
+            Some(range) if range.contains(&upper) => {}
+            Some(range) => return Err(WorldError::EntityIdTaken(range.upper_bound.unwrap() + 1)),
+            None => return Err(WorldError::EntityIdTaken(lower)),
+        }
+
+        self.valid_entities.remove(lower, upper);
+        self.reserved_ranges
+            .insert(ValidEntityRange::new(lower, Some(upper)));
+        Ok(())
+    }
+
+    pub fn entity_id_stats(&self) -> EntityIdStats {
+        let mut stats = EntityIdStats::default();
+        for range in self.valid_entities.iter() {
+            match range.len() {
+                Some(len) => {
+                    stats.free_ranges += 1;
+                    stats.free_ids += len;
+                    stats.largest_free_range = stats.largest_free_range.max(len);
+                }
+                None => stats.unbounded_from = range.lower_bound,
+            }
+        }
+        stats
+    }
+
+    // Spawns the reserved entities. Every method that spawns or despawns entities or adds
+    // components flushes first.
+    pub fn flush(&mut self) {
+        let reserved = std::mem::take(self.reserved_entities.get_mut());
+        for _ in 0..reserved {
+            self.spawn_first_valid_entity();
+        }
+    }
+
+    // Whether `entity` was spawned and hasn't been despawned since. Handles kept from before
+    // their id was reused are not alive.
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.live_entities.contains(entity.0) && entity.1 == self.generation_of(entity.0)
+    }
+
+    // Whether the world has `entity`, i.e. it is alive.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.is_alive(entity)
+    }
+
+    // Every live entity in id order, including those without components.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.live_entities
+            .iter()
+            .map(|id| Entity(id, self.generation_of(id)))
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entity_count
     }
 
-    pub fn add_child(&mut self, parent: Entity, child: Entity) {
+    #[allow(clippy::redundant_field_names)]
+    pub fn add_child(&mut self, parent: Entity, child: Entity) -> Result<(), WorldError> {
+        self.flush();
+        self.check_alive(parent)?;
+        self.check_alive(child)?;
         if let Some(children) = self.entity_component_mut::<Children>(parent) {
             children.children.insert(child);
             
         } else {
             let mut children = Children { children: HashSet::new() };
             children.children.insert(child);
-            self.enable_component_for_entity(parent, children);
+            self.enable_component_for_entity(parent, children)?;
         }
-        self.enable_component_for_entity(child, Parent {parent: parent});
+        self.enable_component_for_entity(child, Parent {parent: parent})
     }
 
     pub fn remove_child(&mut self, parent: Entity, child: Entity) {
@@ -118,22 +638,44 @@ impl World {
     where
         T: Component + 'static,
     {
-        let component_hash = T::hash();
-        let node_id = NodeId([entity.0, component_hash]);
-        self.node_data
-            .get(&node_id)
-            .map(|data| data.as_any().downcast_ref::<T>().unwrap())
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.component_ref::<T>(NodeId([entity.0, T::hash()]))
     }
 
     pub fn entity_component_mut<T>(&mut self, entity: Entity) -> Option<&mut T>
     where
-        T: Component + 'static,
+        T: MutableComponent + 'static,
     {
-        let component_hash = T::hash();
-        let node_id = NodeId([entity.0, component_hash]);
-        self.node_data
-            .get_mut(&node_id)
-            .map(|data| data.as_any_mut().downcast_mut::<T>().unwrap())
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.component_mut::<T>(NodeId([entity.0, T::hash()]))
+    }
+
+    pub fn entity_dynamic_component(
+        &self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Option<&DynamicComponent> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.column::<DynamicComponent>(component)
+            .and_then(|column| column.get(entity))
+    }
+
+    pub fn entity_dynamic_component_mut(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Option<&mut DynamicComponent> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.column_mut::<DynamicComponent>(component)
+            .and_then(|column| column.get_mut(entity))
     }
 
     pub fn node_to_component<T>(&self, node_id: NodeId) -> Option<&T>
@@ -142,9 +684,7 @@ impl World {
     {
         let component_hash = T::hash();
         if component_hash == node_id.0[1] {
-            self.node_data
-                .get(&node_id)
-                .map(|data| data.as_any().downcast_ref::<T>().unwrap())
+            self.component_ref::<T>(node_id)
         } else {
             None
         }
@@ -152,13 +692,11 @@ impl World {
 
     pub fn node_to_component_mut<T>(&mut self, node_id: NodeId) -> Option<&mut T>
     where
-        T: Component + 'static,
+        T: MutableComponent + 'static,
     {
         let component_hash = T::hash();
         if component_hash == node_id.0[1] {
-            self.node_data
-                .get_mut(&node_id)
-                .map(|data| data.as_any_mut().downcast_mut::<T>().unwrap())
+            self.component_mut::<T>(node_id)
         } else {
             None
         }
@@ -171,11 +709,7 @@ impl World {
         let component_hash = T::hash();
         let node_id = NodeId([node_bundle.id, component_hash]);
         if node_bundle.nodes.contains(&node_id) {
-            self.node_data
-                .get(&node_id)
-                .unwrap()
-                .as_any()
-                .downcast_ref::<T>()
+            self.component_ref::<T>(node_id)
         } else {
             None
         }
@@ -183,23 +717,62 @@ impl World {
 
     pub fn unpack_mut<T>(&mut self, node_bundle: &NodeBundle) -> Option<&mut T>
     where
-        T: Component + 'static,
+        T: MutableComponent + 'static,
     {
         let component_hash = T::hash();
         let node_id = NodeId([node_bundle.id, component_hash]);
         if node_bundle.nodes.contains(&node_id) {
-            self.node_data
-                .get_mut(&node_id)
-                .unwrap()
-                .as_any_mut()
-                .downcast_mut::<T>()
+            self.component_mut::<T>(node_id)
+        } else {
+            None
+        }
+    }
+
+    pub fn unpack_dynamic(
+        &self,
+        node_bundle: &NodeBundle,
+        component: ComponentId,
+    ) -> Option<&DynamicComponent> {
+        let node_id = NodeId([node_bundle.id, self.registry.get(component)?.hash()]);
+        if node_bundle.nodes.contains(&node_id) {
+            self.entity_dynamic_component(self.entity_of(node_bundle), component)
+        } else {
+            None
+        }
+    }
+
+    pub fn unpack_dynamic_mut(
+        &mut self,
+        node_bundle: &NodeBundle,
+        component: ComponentId,
+    ) -> Option<&mut DynamicComponent> {
+        let node_id = NodeId([node_bundle.id, self.registry.get(component)?.hash()]);
+        if node_bundle.nodes.contains(&node_id) {
+            let entity = self.entity_of(node_bundle);
+            self.entity_dynamic_component_mut(entity, component)
         } else {
             None
         }
     }
 
-    pub fn entity_of(node_bundle: &NodeBundle) -> Entity {
-        Entity(node_bundle.id)
+    // Every stored value of `T`, packed contiguously.
+    pub fn component_column<T>(&self) -> Option<&Column<T>>
+    where
+        T: Component + 'static,
+    {
+        self.column::<T>(self.component_id::<T>()?)
+    }
+
+    pub fn component_column_mut<T>(&mut self) -> Option<&mut Column<T>>
+    where
+        T: MutableComponent + 'static,
+    {
+        let component = self.component_id::<T>()?;
+        self.column_mut::<T>(component)
+    }
+
+    pub fn entity_of(&self, node_bundle: &NodeBundle) -> Entity {
+        Entity(node_bundle.id, self.generation_of(node_bundle.id))
     }
 
     pub fn component_node_bundles(
@@ -208,22 +781,108 @@ impl World {
         with: Option<HashSet<usize>>,
         without: Option<HashSet<usize>>,
     ) -> Vec<NodeBundle> {
-        let component_filter = NodeFilter {
-            get: get.unwrap_or_default(),
-            with: with.unwrap_or_default(),
-            without: without.unwrap_or_default(),
+        let get = get.unwrap_or_default();
+        let required = get.iter().chain(with.iter().flatten()).copied();
+        let Some(component_filter) = self.component_filter(required, without.into_iter().flatten())
+        else {
+            return Vec::new();
         };
 
-        if let Ok(node_bundle) = self
-            .node_table
-            .get_dimension_at_indices(1, component_filter)
-        {
-            node_bundle
-        } else {
-            Vec::new()
-        }
+        let mut node_bundles: Vec<NodeBundle> = self
+            .storage
+            .matching_entities(&component_filter)
+            .map(|entity| NodeBundle {
+                id: entity.0,
+                nodes: get
+                    .iter()
+                    .map(|component_hash| NodeId([entity.0, *component_hash]))
+                    .collect(),
+            })
+            .collect();
+        // Not every storage yields its matches in order
+        node_bundles.sort_unstable_by_key(|bundle| bundle.id);
+        node_bundles
+    }
+
+    // The id the component was given when it was registered, if it was registered as `T`.
+    pub fn component_id<T>(&self) -> Option<ComponentId>
+    where
+        T: Component + 'static,
+    {
+        self.registry
+            .id_of(T::hash())
+            .filter(|_| self.registry.is_registered_as::<T>())
+    }
+
+    // Translates component hashes into a storage filter. `None` if a required component isn't
+    // registered, as nothing can match then.
+    pub(crate) fn component_filter(
+        &self,
+        required: impl IntoIterator<Item = usize>,
+        excluded: impl IntoIterator<Item = usize>,
+    ) -> Option<ComponentFilter> {
+        let required = required
+            .into_iter()
+            .map(|component_hash| self.registry.id_of(component_hash))
+            .collect::<Option<Vec<ComponentId>>>()?;
+        let excluded = excluded
+            .into_iter()
+            .filter_map(|component_hash| self.registry.id_of(component_hash))
+            .collect();
+
+        Some(ComponentFilter { required, excluded })
+    }
+
+    // Lazily yields every entity that has all the queried components, along with them.
+    pub fn query<Q>(&self) -> Query<'_, Q>
+    where
+        Q: ReadOnlyQueryData,
+    {
+        self.query_filtered::<Q>(None, None)
+    }
+
+    pub fn query_filtered<Q>(
+        &self,
+        with: Option<HashSet<usize>>,
+        without: Option<HashSet<usize>>,
+    ) -> Query<'_, Q>
+    where
+        Q: ReadOnlyQueryData,
+    {
+        // SAFETY: the query only reads, and the world is borrowed for as long as it lives.
+        unsafe { Query::new(NonNull::from(self), with, without) }
+    }
+
+    pub fn query_mut<Q>(&mut self) -> Query<'_, Q>
+    where
+        Q: QueryData,
+    {
+        self.query_filtered_mut::<Q>(None, None)
+    }
+
+    // Panics if the query names the same component twice, as it could then hand out two
+    // mutable references to one value.
+    pub fn query_filtered_mut<Q>(
+        &mut self,
+        with: Option<HashSet<usize>>,
+        without: Option<HashSet<usize>>,
+    ) -> Query<'_, Q>
+    where
+        Q: QueryData,
+    {
+        let mut hashes = Vec::new();
+        Q::component_hashes(&mut hashes);
+        let mut unique_hashes = HashSet::new();
+        assert!(
+            hashes.into_iter().all(|hash| unique_hashes.insert(hash)),
+            "a query can't access the same component more than once"
+        );
+
+        // SAFETY: the world is borrowed mutably for as long as the query lives.
+        unsafe { Query::new(NonNull::from(self), with, without) }
     }
 
+    #[allow(mismatched_lifetime_syntaxes)]
     pub fn ecs_events_iter(&self) -> Iter<ECSEvent> {
         self.ecs_events.iter()
     }
@@ -232,59 +891,337 @@ impl World {
         self.ecs_events = Vec::new();
     }
 
-    fn sort_entity_ranges(&mut self) {
-        self.valid_entities
-            .sort_by(|a, b| a.lower_bound.cmp(&b.lower_bound));
+    fn insert_component_data(
+        &mut self,
+        entity: Entity,
+        component_id: ComponentId,
+        component: Box<dyn Component>,
+    ) -> Result<(), WorldError> {
+        let Some(info) = self.registry.get(component_id) else {
+            return Err(WorldError::UnregisteredComponent(component_id));
+        };
+        let is_marker = info.is_marker();
+        let new_column = info.new_column_fn();
+
+        self.insert_component_with(entity, component_id, |world, was_enabled| {
+            if is_marker {
+                was_enabled.then_some(component)
+            } else {
+                world
+                    .storage
+                    .column_or_insert_with(component_id, &new_column)
+                    .insert_boxed(entity, component)
+            }
+        })
     }
 
-    fn remove_valid_entity(&mut self, index: usize) {
-        for (range_index, old_range) in self.valid_entities.iter_mut().enumerate() {
-            if old_range.contains(&index) {
-                if let Some(new_range) = old_range.split_at(&index) {
-                    if new_range.is_valid() {
-                        if old_range.is_valid() {
-                            self.valid_entities.push(new_range);
-                        } else {
-                            *old_range = new_range;
-                        }
-                    } else if !old_range.is_valid() {
-                        self.valid_entities.swap_remove(range_index);
-                    }
-                } else if !old_range.is_valid() {
-                    self.valid_entities.swap_remove(range_index);
-                }
+    // Enables the component in storage and lets `store` put the value into its column,
+    // returning the value it replaced. Markers are only enabled, they have no column.
+    fn insert_component_with(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+        store: impl FnOnce(&mut Self, bool) -> Option<Box<dyn Component>>,
+    ) -> Result<(), WorldError> {
+        self.resolve_exclusivity(entity, component)?;
+
+        let was_enabled = self.storage.enable(entity, component);
+        if let Some(old_data) = store(self, was_enabled) {
+            self.ecs_events
+                .push(ECSEvent::ComponentChanged(entity, old_data));
+            self.insert_required_components(entity, component);
+            self.run_hook(component, entity, |hooks| hooks.on_replace);
+        } else {
+            self.ecs_events
+                .push(ECSEvent::ComponentAdded(entity, component));
+            self.insert_required_components(entity, component);
+            self.run_hook(component, entity, |hooks| hooks.on_add);
+        }
+        Ok(())
+    }
 
+    // Makes room for `component` among the members of its exclusive group, if it has one.
+    fn resolve_exclusivity(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Result<(), WorldError> {
+        let Some(group) = self.registry.exclusive_group_of(component) else {
+            return Ok(());
+        };
+        let policy = group.policy();
+        let member_count = group.members().len();
+
+        for member_index in 0..member_count {
+            // Removal hooks may change the registry, so the group is looked up every time.
+            let Some(member) = self
+                .registry
+                .exclusive_group_of(component)
+                .and_then(|group| group.members().get(member_index).copied())
+            else {
                 break;
+            };
+
+            if member == component || !self.storage.contains(entity, member) {
+                continue;
+            }
+
+            match policy {
+                ExclusivityPolicy::Replace => self.remove_component_data(entity, member),
+                ExclusivityPolicy::Reject => {
+                    return Err(WorldError::ExclusiveComponentConflict {
+                        entity,
+                        component,
+                        conflicting: member,
+                    })
+                }
             }
         }
-        self.sort_entity_ranges();
+        Ok(())
     }
 
-    fn add_valid_entity(&mut self, index: usize) {
-        let mut range_glob: ValidEntityRange = ValidEntityRange {
-            lower_bound: index,
-            upper_bound: Some(index),
-        };
+    fn insert_required_components(&mut self, entity: Entity, component: ComponentId) {
+        let required_count = self.registry.requirements_of(component).len();
+
+        for required_index in 0..required_count {
+            let requirement = self.registry.requirements_of(component)[required_index];
+            if !self.storage.contains(entity, requirement.component) {
+                // Inserting it also inserts whatever it requires in turn. A required component
+                // rejected by its exclusive group is left out rather than failing the insert.
+                let _ = self.insert_component_data(
+                    entity,
+                    requirement.component,
+                    (requirement.default_fn)(),
+                );
+            }
+        }
+    }
+
+    fn remove_component_data(&mut self, entity: Entity, component: ComponentId) {
+        if !self.storage.contains(entity, component) {
+            return;
+        }
+
+        self.run_remove_hook(component, entity);
+        let marker = self
+            .registry
+            .get(component)
+            .and_then(|info| info.marker());
+        if self.storage.disable(entity, component) {
+            let old_data = match marker {
+                Some(marker) => Some(marker),
+                None => self
+                    .storage
+                    .column_mut(component)
+                    .and_then(|column| column.remove_boxed(entity.0)),
+            };
+            if let Some(old_data) = old_data {
+                self.ecs_events
+                    .push(ECSEvent::ComponentRemoved(entity, old_data));
+            }
+        }
+    }
 
-        let mut new_valid_ranges: Vec<ValidEntityRange> = Vec::new();
+    fn component_ref<T>(&self, node_id: NodeId) -> Option<&T>
+    where
+        T: Component + 'static,
+    {
+        if is_marker::<T>() {
+            if self.registry.is_registered_as::<T>() && self.contains_node(node_id) {
+                // SAFETY: `T` is zero-sized and a value of it was inserted, so it is inhabited
+                // and a dangling, well-aligned pointer is valid for the zero bytes it covers.
+                Some(unsafe { NonNull::<T>::dangling().as_ref() })
+            } else {
+                None
+            }
+        } else {
+            self.column::<T>(self.registry.id_of(node_id.0[1])?)
+                .and_then(|column| column.get_by_id(node_id.0[0]))
+        }
+    }
 
-        for old_range in self.valid_entities.iter() {
-            if !range_glob.merge_with(old_range) {
-                new_valid_ranges.push(old_range.clone());
+    fn component_mut<T>(&mut self, node_id: NodeId) -> Option<&mut T>
+    where
+        T: MutableComponent + 'static,
+    {
+        if is_marker::<T>() {
+            if self.registry.is_registered_as::<T>() && self.contains_node(node_id) {
+                // SAFETY: see `component_ref`; writes through a zero-sized reference touch no memory.
+                Some(unsafe { NonNull::<T>::dangling().as_mut() })
+            } else {
+                None
             }
+        } else {
+            let component = self.registry.id_of(node_id.0[1])?;
+            self.column_mut::<T>(component)
+                .and_then(|column| column.get_by_id_mut(node_id.0[0]))
         }
+    }
+
+    // Columns only ever hold the type registered for their id, so a failed downcast means
+    // `T` isn't that type.
+    pub(crate) fn column<T>(&self, component: ComponentId) -> Option<&Column<T>>
+    where
+        T: Component + 'static,
+    {
+        self.storage
+            .column(component)
+            .and_then(|column| column.as_any().downcast_ref::<Column<T>>())
+    }
 
-        new_valid_ranges.push(range_glob);
+    pub(crate) fn column_mut<T>(&mut self, component: ComponentId) -> Option<&mut Column<T>>
+    where
+        T: Component + 'static,
+    {
+        self.storage
+            .column_mut(component)
+            .and_then(|column| column.as_any_mut().downcast_mut::<Column<T>>())
+    }
+
+    pub(crate) fn reserve_component<T>(&mut self, additional: usize)
+    where
+        T: Component + 'static,
+    {
+        if let Some(component) = self.component_id::<T>().filter(|_| !is_marker::<T>()) {
+            self.column_or_insert::<T>(component).reserve(additional);
+        }
+    }
 
-        self.valid_entities = new_valid_ranges;
+    // Stores a value of `T` for each of the freshly spawned `entities`, without events or hooks.
+    pub(crate) fn insert_spawned<T>(
+        &mut self,
+        component: ComponentId,
+        entities: &[Entity],
+        values: Vec<T>,
+    ) where
+        T: Component + 'static,
+    {
+        for entity in entities.iter() {
+            self.storage.enable(*entity, component);
+        }
+        if !is_marker::<T>() {
+            let column = self.column_or_insert::<T>(component);
+            for (entity, value) in entities.iter().zip(values) {
+                column.insert(*entity, value);
+            }
+        }
+    }
 
-        self.sort_entity_ranges();
+    // Only called once `T` has been registered under `component`.
+    fn column_or_insert<T>(&mut self, component: ComponentId) -> &mut Column<T>
+    where
+        T: Component + 'static,
+    {
+        self.storage
+            .column_or_insert_with(component, &|| Box::new(Column::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Column<T>>()
+            .expect("component column does not match its registered type")
     }
 
-    fn first_valid_entity(&self) -> Option<usize> {
+    fn contains_node(&self, node_id: NodeId) -> bool {
+        self.registry
+            .id_of(node_id.0[1])
+            .is_some_and(|component| self.storage.contains(Entity::new(node_id.0[0]), component))
+    }
+
+    fn run_hook(
+        &mut self,
+        component: ComponentId,
+        entity: Entity,
+        select: fn(&ComponentHooks) -> Option<ComponentHook>,
+    ) {
+        if let Some(hook) = self
+            .registry
+            .get(component)
+            .and_then(|info| select(info.hooks()))
+        {
+            hook(self, entity);
+        }
+    }
+
+    // Whether enabling any of the components leaves the others alone: no component is listed
+    // twice, and no two share an exclusive group.
+    fn are_independent(&self, components: &[ComponentId]) -> bool {
+        let group_of = |component: &ComponentId| {
+            self.registry
+                .get(*component)
+                .and_then(|info| info.exclusive_group())
+        };
+        components.iter().enumerate().all(|(index, component)| {
+            components[..index].iter().all(|other| {
+                other != component
+                    && (group_of(component).is_none() || group_of(other) != group_of(component))
+            })
+        })
+    }
+
+    // Skipped while the hook already runs for this component of the entity, e.g. when it removes
+    // the component itself or despawns the entity.
+    fn run_remove_hook(&mut self, component: ComponentId, entity: Entity) {
+        if self.removing.contains(&(entity.0, component)) {
+            return;
+        }
+        self.removing.push((entity.0, component));
+        self.run_hook(component, entity, |hooks| hooks.on_remove);
+        self.removing.pop();
+    }
+
+    fn check_alive(&self, entity: Entity) -> Result<(), WorldError> {
+        match self.is_alive(entity) {
+            true => Ok(()),
+            false => Err(WorldError::EntityNotAlive(entity)),
+        }
+    }
+
+    fn is_reserved_id(&self, id: usize) -> bool {
+        self.reserved_ranges.contains(&id)
+    }
+
+    fn spawn_first_valid_entity(&mut self) -> Entity {
+        let entity_id = self.first_valid_entity().unwrap();
+        self.remove_valid_entity(entity_id);
+        let new_entity = Entity(entity_id, self.generation_of(entity_id));
+        self.mark_alive(entity_id);
+        self.ecs_events.push(ECSEvent::EntitySpawned(new_entity));
+        new_entity
+    }
+
+    fn mark_alive(&mut self, id: usize) {
+        self.live_entities.insert(id);
+        self.entity_count += 1;
+    }
+
+    fn generation_of(&self, id: usize) -> u32 {
+        self.generations.get(id).copied().unwrap_or_default()
+    }
+
+    fn dynamic_component_info(&self, component: ComponentId) -> Result<&ComponentInfo, WorldError> {
+        match self.registry.get(component) {
+            Some(info) if info.is_dynamic() => Ok(info),
+            Some(_) => Err(WorldError::NotDynamicComponent(component)),
+            None => Err(WorldError::UnregisteredComponent(component)),
+        }
+    }
+
+    fn remove_valid_entity(&mut self, index: usize) {
+        self.valid_entities.remove(index, index);
+    }
+
+    fn add_valid_entity(&mut self, index: usize) {
+        self.valid_entities
+            .insert(ValidEntityRange::new(index, Some(index)));
+    }
+
+    // The last range is unbounded, so there always is one that can hold `count` ids.
+    fn take_entity_range(&mut self, count: usize) -> usize {
         self.valid_entities
-            .first()
-            .map(|first_valid_range| first_valid_range.lower_bound)
+            .take_first_fit(count)
+            .expect("the last entity range is unbounded")
+    }
+
+    fn first_valid_entity(&self) -> Option<usize> {
+        self.valid_entities.first()
     }
 }
 
@@ -292,8 +1229,17 @@ impl Debug for World {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("World")
             .field("valid_entities", &self.valid_entities)
-            .field("table_node_count", &self.node_table.size())
-            .field("data_node_count", &self.node_data.len())
+            .field("entity_count", &self.entity_count)
+            .field("table_node_count", &self.storage.len())
+            .field(
+                "data_node_count",
+                &self
+                    .storage
+                    .columns()
+                    .map(|column| column.len())
+                    .sum::<usize>(),
+            )
+            .field("registered_components", &self.registry.len())
             .field("ecs_events_this_tick", &self.ecs_events)
             .finish()
     }
@@ -1,7 +1,10 @@
+#[allow(unused_imports)]
 use custom_ecs::{table::NodeFilter, *};
+#[allow(unused_imports)]
 use ecs_proc_macros::{evaluate_string_var, name_to_type};
 use hashbrown::HashSet;
 
+#[allow(dead_code)]
 mod player {
     use custom_ecs::*;
 
@@ -12,6 +15,7 @@ mod player {
     }
 }
 
+#[allow(dead_code)]
 mod enemy {
     use custom_ecs::*;
 
@@ -28,6 +32,7 @@ mod enemy {
 }
 
 #[test]
+#[allow(unused_variables)]
 fn ecs_test() {
     let mut new_world = World::new();
     for _ in 0..10 {
@@ -38,7 +43,8 @@ fn ecs_test() {
                 max: 1.0,
                 current: 1.0,
             },
-        );
+        )
+        .unwrap();
     }
 
     for bundle in new_world
@@ -51,6 +57,7 @@ fn ecs_test() {
     let id: usize = 0;
 }
 
+#[allow(dead_code)]
 fn pass_type<T>() -> T
 where
     T: Default,
@@ -64,7 +71,1487 @@ fn hash_test() {
     println!("{}", enemy::Health::hash());
 }
 
+#[derive(Debug, Component)]
+#[component(name = "ecs_tests::player::Health")]
+struct RenamedHealth;
+
+#[derive(Debug, Component)]
+#[component(id = 0x5eed)]
+struct FixedId;
+
+#[test]
+fn stable_hash_test() {
+    // Pinned so that a change to the hashing scheme can't slip through unnoticed.
+    assert_eq!(
+        component_hash("custom_ecs::entity::Children"),
+        0x4f11b5846c20bba8_u64 as usize
+    );
+    assert_eq!(
+        Children::hash(),
+        component_hash("custom_ecs::entity::Children")
+    );
+
+    assert_eq!(
+        player::Health::hash(),
+        component_hash("ecs_tests::player::Health")
+    );
+    assert_eq!(
+        enemy::Health::hash(),
+        component_hash("ecs_tests::enemy::Health")
+    );
+    assert_ne!(player::Health::hash(), enemy::Health::hash());
+
+    assert_eq!(RenamedHealth::hash(), player::Health::hash());
+    assert_eq!(FixedId::hash(), 0x5eed);
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Component)]
+#[component(id = 0x5eed)]
+struct CollidesWithFixedId(u32);
+
+#[test]
+fn hash_collision_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+
+    world.enable_component_for_entity(entity, FixedId).unwrap();
+    let error = world
+        .enable_component_for_entity(entity, CollidesWithFixedId(1))
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        WorldError::ComponentHashCollision { hash: 0x5eed, .. }
+    ));
+    let message = error.to_string();
+    assert!(message.contains("FixedId") && message.contains("CollidesWithFixedId"));
+
+    // The rejected type must not be able to reach the registered type's data.
+    assert!(world
+        .entity_component::<CollidesWithFixedId>(entity)
+        .is_none());
+    world.disable_component_for_entity::<CollidesWithFixedId>(entity);
+    assert!(world.entity_component::<FixedId>(entity).is_some());
+}
+
+#[derive(Debug, Component, Clone, Default, PartialEq)]
+#[component(clone, debug, default)]
+struct Position {
+    x: f32,
+    y: f32,
+}
+
+#[test]
+fn registry_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(entity, Position { x: 1.0, y: 2.0 })
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, player::Health::default())
+        .unwrap();
+
+    let info = world
+        .registry()
+        .get(world.component_id::<Position>().unwrap())
+        .unwrap();
+    assert_eq!(info.name(), "ecs_tests::Position");
+    assert_eq!(info.type_id(), Some(std::any::TypeId::of::<Position>()));
+    assert_eq!(info.layout(), std::alloc::Layout::new::<Position>());
+    assert!(world
+        .registry()
+        .get_by_name("ecs_tests::Position")
+        .is_some());
+
+    let position = world.entity_component::<Position>(entity).unwrap();
+    let cloned = info.clone_component(position).unwrap();
+    assert_eq!(cloned.as_any().downcast_ref::<Position>(), Some(position));
+    assert_eq!(
+        format!("{:?}", info.debug(position).unwrap()),
+        "Position { x: 1.0, y: 2.0 }"
+    );
+    let default = info.default_component().unwrap();
+    assert_eq!(
+        default.as_any().downcast_ref::<Position>(),
+        Some(&Position::default())
+    );
+
+    // Types without declared metadata can be extended at runtime.
+    let health = world.component_id::<player::Health>().unwrap();
+    let health_info = world.registry().get(health).unwrap();
+    assert!(health_info.default_component().is_none());
+    world
+        .register_component_info(
+            ComponentInfo::of::<player::Health>().with_default::<player::Health>(),
+        )
+        .unwrap();
+    let health_info = world.registry().get(health).unwrap();
+    assert!(health_info.default_component().is_some());
+
+    // Extending a type keeps the metadata it declares.
+    let tracked = world
+        .register_component_info(ComponentInfo::of::<Tracked>().with_debug::<Tracked>())
+        .unwrap();
+    let tracked_info = world.registry().get(tracked).unwrap();
+    assert!(tracked_info.hooks().on_add.is_some());
+    assert!(tracked_info.debug(&Tracked(1)).is_some());
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Component, PartialEq)]
+#[component(serde)]
+struct Score(u32);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Score {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Score {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Score)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_component_test() {
+    let mut world = World::new();
+    let score = world.register_component::<Score>().unwrap();
+    let info = world.registry().get(score).unwrap();
+
+    let serialized = serde_json::to_string(info.serialize(&Score(42)).unwrap()).unwrap();
+    assert_eq!(serialized, "42");
+
+    let mut deserializer = serde_json::Deserializer::from_str("7");
+    let mut deserializer = <dyn erased_serde::Deserializer>::erase(&mut deserializer);
+    let deserialized = info.deserialize(&mut deserializer).unwrap().unwrap();
+    assert_eq!(
+        deserialized.as_any().downcast_ref::<Score>(),
+        Some(&Score(7))
+    );
+
+    // Components that don't declare it can't be serialized.
+    let position = world.register_component::<Position>().unwrap();
+    let position_info = world.registry().get(position).unwrap();
+    assert!(position_info.serialize(&Position::default()).is_none());
+}
+
+#[derive(Debug, Component, Default, Clone, PartialEq)]
+struct Velocity {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Component)]
+struct Buffer<T> {
+    items: Vec<T>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Component)]
+enum Slot<T> {
+    Empty,
+    Full(T),
+}
+
+#[allow(dead_code)]
+#[derive(Component)]
+union Bits<T: Copy> {
+    value: T,
+    raw: u64,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Component)]
+struct Ring<T, const N: usize> {
+    items: [Option<T>; N],
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Component)]
+struct Marker<'a> {
+    label: &'a str,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Component)]
+#[component(name = "pools::Pool")]
+struct Pool<T> {
+    free: Vec<T>,
+}
+
+#[test]
+fn generic_hash_test() {
+    assert_ne!(Buffer::<Position>::hash(), Buffer::<Velocity>::hash());
+    assert_ne!(Slot::<Position>::hash(), Slot::<Velocity>::hash());
+    assert_ne!(Bits::<u32>::hash(), Bits::<f32>::hash());
+    assert_ne!(Ring::<u8, 4>::hash(), Ring::<u8, 8>::hash());
+    assert_ne!(Ring::<u8, 4>::hash(), Ring::<u16, 4>::hash());
+    assert_ne!(Buffer::<Position>::hash(), Slot::<Position>::hash());
+    assert_eq!(
+        Marker::<'static>::hash(),
+        component_hash("ecs_tests::Marker")
+    );
+
+    assert_eq!(
+        Buffer::<Position>::hash(),
+        component_hash_combine(component_hash("ecs_tests::Buffer"), Position::hash())
+    );
+    assert_eq!(
+        Buffer::<Position>::name(),
+        std::any::type_name::<Buffer<Position>>()
+    );
+    assert_eq!(
+        Buffer::<u32>::hash(),
+        component_hash_combine(component_hash("ecs_tests::Buffer"), component_hash("u32"))
+    );
+    assert_ne!(Buffer::<Vec<u8>>::hash(), Buffer::<Vec<i8>>::hash());
+
+    // A name override is kept for every monomorphization.
+    assert_eq!(Pool::<u8>::name(), "pools::Pool");
+    assert_eq!(
+        Pool::<u8>::hash(),
+        component_hash_combine(component_hash("pools::Pool"), component_hash("u8"))
+    );
+
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(
+            entity,
+            Buffer {
+                items: vec![Position::default()],
+            },
+        )
+        .unwrap();
+    world
+        .enable_component_for_entity(
+            entity,
+            Buffer {
+                items: vec![Velocity::default(); 2],
+            },
+        )
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Bits::<u32> { raw: 0 })
+        .unwrap();
+
+    assert_eq!(
+        world
+            .entity_component::<Buffer<Position>>(entity)
+            .unwrap()
+            .items
+            .len(),
+        1
+    );
+    assert_eq!(
+        world
+            .entity_component::<Buffer<Velocity>>(entity)
+            .unwrap()
+            .items
+            .len(),
+        2
+    );
+    assert!(world.entity_component::<Bits<u32>>(entity).is_some());
+    assert!(world.entity_component::<Slot<Position>>(entity).is_none());
+}
+
+#[test]
+fn dynamic_component_test() {
+    let mut world = World::new();
+    let layout = std::alloc::Layout::new::<[u32; 2]>();
+    let scripted = world
+        .register_dynamic_component("mods::Scripted", layout)
+        .unwrap();
+    assert_eq!(
+        world.registry().get(scripted).unwrap().hash(),
+        component_hash("mods::Scripted")
+    );
+    assert_eq!(
+        world
+            .register_dynamic_component("mods::Scripted", layout)
+            .unwrap(),
+        scripted
+    );
+    assert!(world.registry().get(scripted).unwrap().is_dynamic());
+
+    let first = world.alloc_entity();
+    let second = world.alloc_entity();
+    world
+        .enable_component_for_entity(first, Position::default())
+        .unwrap();
+    world
+        .enable_component_for_entity(second, Position::default())
+        .unwrap();
+    world
+        .enable_dynamic_component_for_entity(second, scripted, [1u8; 8])
+        .unwrap();
+
+    assert!(matches!(
+        world.enable_dynamic_component_for_entity(first, scripted, [1u8; 4]),
+        Err(WorldError::DynamicComponentSizeMismatch {
+            expected: 8,
+            found: 4,
+            ..
+        })
+    ));
+    assert!(matches!(
+        world.enable_dynamic_component_for_entity(
+            first,
+            world.component_id::<Position>().unwrap(),
+            [1u8; 8]
+        ),
+        Err(WorldError::NotDynamicComponent(_))
+    ));
+
+    world
+        .entity_dynamic_component_mut(second, scripted)
+        .unwrap()
+        .bytes_mut()[0] = 7;
+
+    let bundles = world.component_node_bundles(
+        Some(component_set!(Position)),
+        Some(hashset!(component_hash("mods::Scripted"))),
+        None,
+    );
+    assert_eq!(bundles.len(), 1);
+    assert_eq!(world.entity_of(&bundles[0]), second);
+
+    let bundles =
+        world.component_node_bundles(Some(hashset!(component_hash("mods::Scripted"))), None, None);
+    let data = world.unpack_dynamic(&bundles[0], scripted).unwrap();
+    assert_eq!(data.bytes(), &[7, 1, 1, 1, 1, 1, 1, 1]);
+
+    world
+        .disable_dynamic_component_for_entity(second, scripted)
+        .unwrap();
+    assert!(world.entity_dynamic_component(second, scripted).is_none());
+
+    // The bytes are aligned as the registered layout requires.
+    let layout = std::alloc::Layout::from_size_align(8, 64).unwrap();
+    let aligned = world
+        .register_dynamic_component("mods::Aligned", layout)
+        .unwrap();
+    world
+        .enable_dynamic_component_for_entity(first, aligned, 7u64.to_ne_bytes())
+        .unwrap();
+    let data = world.entity_dynamic_component(first, aligned).unwrap();
+    assert_eq!(data.bytes().as_ptr() as usize % 64, 0);
+    assert_eq!(data.layout(), layout);
+    assert_eq!(u64::from_ne_bytes(data.bytes().try_into().unwrap()), 7);
+}
+
+#[derive(Debug, Component)]
+#[component(on_add = index_tracked, on_replace = reindex_tracked, on_remove = unindex_tracked)]
+struct Tracked(u32);
+
+#[derive(Debug, Component, PartialEq)]
+struct Indexed(u32);
+
+fn index_tracked(world: &mut World, entity: Entity) {
+    let value = world.entity_component::<Tracked>(entity).unwrap().0;
+    world
+        .enable_component_for_entity(entity, Indexed(value))
+        .unwrap();
+}
+
+fn reindex_tracked(world: &mut World, entity: Entity) {
+    let value = world.entity_component::<Tracked>(entity).unwrap().0;
+    world.entity_component_mut::<Indexed>(entity).unwrap().0 = value;
+}
+
+fn unindex_tracked(world: &mut World, entity: Entity) {
+    assert!(world.entity_component::<Tracked>(entity).is_some());
+    world.disable_component_for_entity::<Indexed>(entity);
+}
+
+static VELOCITY_REMOVALS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[test]
+fn component_hooks_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+
+    world
+        .enable_component_for_entity(entity, Tracked(1))
+        .unwrap();
+    assert_eq!(world.entity_component::<Indexed>(entity), Some(&Indexed(1)));
+
+    world
+        .enable_component_for_entity(entity, Tracked(2))
+        .unwrap();
+    assert_eq!(world.entity_component::<Indexed>(entity), Some(&Indexed(2)));
+
+    world.disable_component_for_entity::<Tracked>(entity);
+    assert!(world.entity_component::<Indexed>(entity).is_none());
+
+    // Hooks can also be installed at runtime, and run when an entity is deallocated.
+    let velocity = world.register_component::<Velocity>().unwrap();
+    world.component_hooks_mut(velocity).unwrap().on_remove = Some(|_, _| {
+        VELOCITY_REMOVALS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    });
+    world
+        .enable_component_for_entity(entity, Velocity::default())
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Tracked(3))
+        .unwrap();
+    world.dealloc_entity(entity).unwrap();
+    assert_eq!(
+        VELOCITY_REMOVALS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+    assert!(world.entity_component::<Indexed>(entity).is_none());
+}
+
+#[derive(Debug, Component)]
+#[component(on_remove = despawn_doomed)]
+struct Doomed;
+
+static DOOMED_REMOVALS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn despawn_doomed(world: &mut World, entity: Entity) {
+    DOOMED_REMOVALS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    world.dealloc_entity(entity).unwrap();
+}
+
+#[test]
+fn reentrant_remove_hook_test() {
+    let mut world = World::new();
+    let first = world.alloc_entity();
+    world.enable_component_for_entity(first, Doomed).unwrap();
+    world
+        .enable_component_for_entity(first, Position::default())
+        .unwrap();
+    world.dealloc_entity(first).unwrap();
+    assert!(!world.is_alive(first));
+
+    // Removing the component despawns the entity, without running the hook a second time.
+    let second = world.alloc_entity();
+    world.enable_component_for_entity(second, Doomed).unwrap();
+    world.disable_component_for_entity::<Doomed>(second);
+    assert!(!world.is_alive(second));
+    assert_eq!(world.entity_count(), 0);
+    assert!(world.component_column::<Position>().unwrap().is_empty());
+    assert_eq!(DOOMED_REMOVALS.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+#[derive(Debug, Component, Default, PartialEq)]
+#[component(requires(Velocity, Visibility))]
+struct Projectile;
+
+#[derive(Debug, Component, Default, PartialEq)]
+#[component(requires(Position))]
+struct Visibility(bool);
+
+#[test]
+fn required_components_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(entity, Velocity { x: 1.0, y: 0.0 })
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Projectile)
+        .unwrap();
+
+    // Existing components are kept, missing ones are defaulted recursively.
+    assert_eq!(
+        world.entity_component::<Velocity>(entity),
+        Some(&Velocity { x: 1.0, y: 0.0 })
+    );
+    assert_eq!(
+        world.entity_component::<Visibility>(entity),
+        Some(&Visibility(false))
+    );
+    assert_eq!(
+        world.entity_component::<Position>(entity),
+        Some(&Position::default())
+    );
+
+    let projectile = world.component_id::<Projectile>().unwrap();
+    let mut required = world.registry().required_components_of(projectile);
+    required.sort();
+    let mut expected = vec![
+        world.component_id::<Velocity>().unwrap(),
+        world.component_id::<Visibility>().unwrap(),
+        world.component_id::<Position>().unwrap(),
+    ];
+    expected.sort();
+    assert_eq!(required, expected);
+}
+
+#[derive(Debug, Component, PartialEq)]
+struct Enemy;
+
+#[test]
+fn marker_component_test() {
+    let mut world = World::new();
+    let mut enemies = Vec::new();
+    for index in 0..100 {
+        let entity = world.alloc_entity();
+        world.enable_component_for_entity(entity, Enemy).unwrap();
+        if index % 2 == 0 {
+            world
+                .enable_component_for_entity(entity, Position::default())
+                .unwrap();
+        }
+        enemies.push(entity);
+    }
+    let enemy = world.component_id::<Enemy>().unwrap();
+    assert!(world.registry().get(enemy).unwrap().is_marker());
+
+    // Only the positions are stored as data.
+    assert_eq!(world.component_column::<Position>().unwrap().len(), 50);
+    assert!(world.component_column::<Enemy>().is_none());
+    assert!(world.storage().column(enemy).is_none());
+    assert_eq!(world.entity_component::<Enemy>(enemies[1]), Some(&Enemy));
+    assert!(world.entity_component_mut::<Enemy>(enemies[1]).is_some());
+
+    let bundles = world.component_node_bundles(Some(component_set!(Enemy, Position)), None, None);
+    assert_eq!(bundles.len(), 50);
+    assert_eq!(world.unpack::<Enemy>(&bundles[0]), Some(&Enemy));
+
+    world.tick();
+    world
+        .enable_component_for_entity(enemies[0], Enemy)
+        .unwrap();
+    world.disable_component_for_entity::<Enemy>(enemies[0]);
+    assert!(world.entity_component::<Enemy>(enemies[0]).is_none());
+    let events: Vec<_> = world.ecs_events_iter().collect();
+    assert!(
+        matches!(events[0], events::ECSEvent::ComponentChanged(entity, _) if *entity == enemies[0])
+    );
+    match events[1] {
+        events::ECSEvent::ComponentRemoved(entity, old_data) => {
+            assert_eq!(*entity, enemies[0]);
+            assert_eq!(old_data.as_any().downcast_ref::<Enemy>(), Some(&Enemy));
+        }
+        _ => panic!("expected a removal event"),
+    }
+}
+
+#[derive(Debug, Component, PartialEq)]
+#[component(immutable)]
+struct Faction(u32);
+
+#[test]
+fn immutable_component_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(entity, Faction(1))
+        .unwrap();
+
+    // The only way to change it is a full replacement, which is observed as an event.
+    world.tick();
+    world
+        .enable_component_for_entity(entity, Faction(2))
+        .unwrap();
+    assert_eq!(world.entity_component::<Faction>(entity), Some(&Faction(2)));
+    match world.ecs_events_iter().next() {
+        Some(events::ECSEvent::ComponentChanged(_, old_data)) => {
+            assert_eq!(
+                old_data.as_any().downcast_ref::<Faction>(),
+                Some(&Faction(1))
+            );
+        }
+        _ => panic!("expected a change event"),
+    }
+}
+
+#[derive(Debug, Component, PartialEq)]
+#[component(exclusive = "state")]
+struct Idle;
+
+#[derive(Debug, Component, PartialEq)]
+#[component(exclusive = "state")]
+struct Walking {
+    speed: f32,
+}
+
+#[derive(Debug, Component, PartialEq)]
+#[component(exclusive = "state")]
+struct Attacking;
+
+#[test]
+fn exclusive_components_test() {
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world.enable_component_for_entity(entity, Idle).unwrap();
+    world.tick();
+
+    world
+        .enable_component_for_entity(entity, Walking { speed: 2.0 })
+        .unwrap();
+    assert!(world.entity_component::<Idle>(entity).is_none());
+    assert!(world.entity_component::<Walking>(entity).is_some());
+    let events: Vec<_> = world.ecs_events_iter().collect();
+    assert!(
+        matches!(events[0], events::ECSEvent::ComponentRemoved(_, old_data) if old_data.as_any().is::<Idle>())
+    );
+    let walking = world.component_id::<Walking>().unwrap();
+    assert!(matches!(events[1], events::ECSEvent::ComponentAdded(_, id) if *id == walking));
+
+    // Replacing a member with itself is not a conflict.
+    world
+        .enable_component_for_entity(entity, Walking { speed: 3.0 })
+        .unwrap();
+
+    world.register_component::<Attacking>().unwrap();
+    let group = world.registry().exclusive_group("state").unwrap();
+    assert_eq!(group.members().len(), 3);
+    world
+        .set_exclusive_group("state", &[], ExclusivityPolicy::Reject)
+        .unwrap();
+    assert!(matches!(
+        world.enable_component_for_entity(entity, Attacking),
+        Err(WorldError::ExclusiveComponentConflict { conflicting, .. }) if conflicting == walking
+    ));
+    assert_eq!(
+        world.entity_component::<Walking>(entity),
+        Some(&Walking { speed: 3.0 })
+    );
+
+    // Groups can also be declared at runtime.
+    world
+        .set_exclusive_group(
+            "motion",
+            &[ComponentId::new(world.registry().len())],
+            ExclusivityPolicy::Replace,
+        )
+        .unwrap_err();
+    let position = world.register_component::<Position>().unwrap();
+    let velocity = world.register_component::<Velocity>().unwrap();
+    world
+        .set_exclusive_group("motion", &[position, velocity], ExclusivityPolicy::Replace)
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Position::default())
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Velocity::default())
+        .unwrap();
+    assert!(world.entity_component::<Position>(entity).is_none());
+}
+
+#[test]
+fn component_column_test() {
+    let mut world = World::new();
+    let entities: Vec<Entity> = (0..1000).map(|_| world.alloc_entity()).collect();
+    for (index, entity) in entities.iter().enumerate() {
+        world
+            .enable_component_for_entity(
+                *entity,
+                Velocity {
+                    x: index as f32,
+                    y: 0.0,
+                },
+            )
+            .unwrap();
+    }
+    world.disable_component_for_entity::<Velocity>(entities[10]);
+    world.dealloc_entity(entities[20]).unwrap();
+
+    let column = world.component_column::<Velocity>().unwrap();
+    assert_eq!(column.len(), 998);
+    assert!(!column.contains(entities[10]));
+    // The last value was moved into the first hole.
+    assert_eq!(column.get(entities[999]).unwrap().x, 999.0);
+    assert_eq!(column.values()[10].x, 999.0);
+    for (entity, velocity) in column.iter() {
+        assert_eq!(world.entity_component::<Velocity>(entity), Some(velocity));
+    }
+
+    for velocity in world
+        .component_column_mut::<Velocity>()
+        .unwrap()
+        .values_mut()
+    {
+        velocity.y = 1.0;
+    }
+    assert_eq!(
+        world.entity_component::<Velocity>(entities[500]).unwrap().y,
+        1.0
+    );
+    assert!(world.component_column::<Position>().is_none());
+}
+
+// Runs `test` against a world on every storage backend.
+fn for_each_storage(test: fn(World)) {
+    test(World::with_storage(storage::TableStorage::new()));
+    test(World::with_storage(storage::SparseSetStorage::new()));
+}
+
+fn query_storage_backend(mut world: World) {
+    let entities: Vec<Entity> = (0..50).map(|_| world.alloc_entity()).collect();
+    for (index, entity) in entities.iter().enumerate() {
+        world
+            .enable_component_for_entity(
+                *entity,
+                Velocity {
+                    x: index as f32,
+                    y: 0.0,
+                },
+            )
+            .unwrap();
+        if index % 3 == 0 {
+            world.enable_component_for_entity(*entity, Enemy).unwrap();
+        }
+        if index % 5 == 0 {
+            world
+                .enable_component_for_entity(*entity, Position::default())
+                .unwrap();
+        }
+    }
+    world.disable_component_for_entity::<Velocity>(entities[30]);
+    world.dealloc_entity(entities[15]).unwrap();
+
+    let enemy = world.component_id::<Enemy>().unwrap();
+    assert!(world.storage().contains(entities[3], enemy));
+    assert!(!world.storage().contains(entities[15], enemy));
+    assert_eq!(world.storage().components_of(entities[0]).len(), 3);
+
+    let matched: Vec<usize> = world
+        .component_node_bundles(
+            Some(component_set!(Velocity)),
+            Some(component_set!(Enemy)),
+            Some(component_set!(Position)),
+        )
+        .iter()
+        .map(|bundle| {
+            assert_eq!(
+                world.unpack::<Velocity>(bundle).unwrap().x,
+                bundle.id() as f32
+            );
+            assert!(world.unpack::<Enemy>(bundle).is_none());
+            bundle.id()
+        })
+        .collect();
+    let expected: Vec<usize> = (0..50)
+        .filter(|index| index % 3 == 0 && index % 5 != 0 && *index != 30)
+        .collect();
+    assert_eq!(matched, expected);
+}
+
+#[test]
+fn storage_backend_test() {
+    for_each_storage(query_storage_backend);
+}
+
+fn matching_entities(
+    storage: &dyn storage::ComponentStorage,
+    required: &[usize],
+    excluded: &[usize],
+) -> HashSet<Entity> {
+    let filter = storage::ComponentFilter {
+        required: required
+            .iter()
+            .map(|index| ComponentId::new(*index))
+            .collect(),
+        excluded: excluded
+            .iter()
+            .map(|index| ComponentId::new(*index))
+            .collect(),
+    };
+    storage.matching_entities(&filter).collect()
+}
+
+#[test]
+fn table_line_order_test() {
+    use storage::ComponentStorage;
+
+    let mut storage = storage::TableStorage::new();
+    // Insert out of order, so nodes land before, between and after existing ones.
+    for step in 0..1000 {
+        let id = step * 7919 % 1000;
+        for component in 1..4 {
+            if id % component == 0 {
+                storage.enable(Entity::new(id), ComponentId::new(component));
+            }
+        }
+    }
+    for entity in (0..1000).step_by(4) {
+        storage.disable(Entity::new(entity), ComponentId::new(1));
+    }
+
+    let expected: HashSet<Entity> = (0..1000)
+        .filter(|entity| entity % 2 == 0 && entity % 4 != 0 && entity % 3 != 0)
+        .map(Entity::new)
+        .collect();
+    assert_eq!(matching_entities(&storage, &[2, 1], &[3]), expected);
+
+    assert_eq!(storage.components_of(Entity::new(6)).len(), 3);
+    assert_eq!(storage.len(), 1000 + 500 + 334 - 250);
+}
+
+#[test]
+fn large_filter_test() {
+    use storage::{ComponentStorage, SparseSetStorage, TableStorage};
+
+    fn matching(storage: &mut dyn ComponentStorage) -> [HashSet<Entity>; 2] {
+        for id in 0..10 {
+            let components = if id < 5 { 0..100 } else { 0..99 };
+            for component in components {
+                storage.enable(Entity::new(id), ComponentId::new(component));
+            }
+        }
+        storage.enable(Entity::new(3), ComponentId::new(200));
+
+        let required: Vec<usize> = (0..100).collect();
+        let excluded: Vec<usize> = (200..240).collect();
+        [
+            matching_entities(storage, &required, &excluded),
+            matching_entities(storage, &[5, 99], &[99]),
+        ]
+    }
+
+    let expected = [[0, 1, 2, 4].map(Entity::new).into(), HashSet::new()];
+    assert_eq!(matching(&mut TableStorage::new()), expected);
+    assert_eq!(matching(&mut SparseSetStorage::new()), expected);
+}
+
+fn run_queries(mut world: World) {
+    let entities: Vec<Entity> = (0..20).map(|_| world.alloc_entity()).collect();
+    for (index, entity) in entities.iter().enumerate() {
+        world
+            .enable_component_for_entity(
+                *entity,
+                Position {
+                    x: index as f32,
+                    y: 0.0,
+                },
+            )
+            .unwrap();
+        if index % 2 == 0 {
+            world
+                .enable_component_for_entity(*entity, Velocity { x: 1.0, y: 2.0 })
+                .unwrap();
+        }
+        if index % 4 == 0 {
+            world.enable_component_for_entity(*entity, Enemy).unwrap();
+        }
+    }
+
+    for (_, (position, velocity)) in world.query_mut::<(&mut Position, &Velocity)>() {
+        position.x += velocity.x;
+        position.y += velocity.y;
+    }
+    assert_eq!(
+        world.entity_component::<Position>(entities[4]),
+        Some(&Position { x: 5.0, y: 2.0 })
+    );
+    assert_eq!(
+        world.entity_component::<Position>(entities[5]),
+        Some(&Position { x: 5.0, y: 0.0 })
+    );
+
+    assert_eq!(world.query::<(&Velocity,)>().count(), 10);
+    assert_eq!(world.query::<(&Position, &Enemy)>().count(), 5);
+    let (found, _) = world
+        .query::<(&Position, &Velocity)>()
+        .find(|(_, (position, _))| position.x == 9.0)
+        .unwrap();
+    assert_eq!(found, entities[8]);
+
+    let wandering: Vec<Entity> = world
+        .query_filtered::<(&Position,)>(Some(component_set!(Velocity)), Some(component_set!(Enemy)))
+        .map(|(entity, _)| entity)
+        .collect();
+    assert_eq!(wandering.len(), 5);
+    assert!(wandering.contains(&entities[2]) && !wandering.contains(&entities[4]));
+
+    // Nothing is registered under these, so nothing can match.
+    assert_eq!(world.query::<(&player::Health, &Position)>().count(), 0);
+}
+
+#[test]
+fn query_test() {
+    for_each_storage(run_queries);
+
+    let mut world = World::new();
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(entity, Velocity::default())
+        .unwrap();
+    let aliasing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.query_mut::<(&mut Velocity, &Velocity)>().count()
+    }));
+    assert!(aliasing.is_err());
+}
+
+#[test]
+fn query_count_test() {
+    let mut world = World::new();
+    for index in 0..300 {
+        let entity = world.alloc_entity();
+        world
+            .enable_component_for_entity(entity, Velocity::default())
+            .unwrap();
+        if index % 3 == 0 {
+            world.enable_component_for_entity(entity, Enemy).unwrap();
+        }
+    }
+
+    assert_eq!(world.query::<(&Velocity,)>().count(), 300);
+    assert_eq!(world.query::<(&Velocity, &Enemy)>().count(), 100);
+    assert_eq!(
+        world
+            .query_filtered::<(&Velocity,)>(None, Some(component_set!(Enemy)))
+            .count(),
+        200
+    );
+
+    let mut enemies = world.query::<(&Enemy,)>();
+    enemies.nth(9);
+    assert_eq!(enemies.count(), 90);
+
+    assert!(!world.query::<(&Enemy,)>().is_empty());
+    assert!(world
+        .query_filtered::<(&Enemy,)>(None, Some(component_set!(Velocity)))
+        .is_empty());
+    assert!(world.query::<(&Position,)>().is_empty());
+}
+
+#[test]
+fn component_id_test() {
+    let mut world = World::new();
+    assert!(world.component_id::<Position>().is_none());
+
+    let entity = world.alloc_entity();
+    world
+        .enable_component_for_entity(entity, Position::default())
+        .unwrap();
+    world
+        .enable_component_for_entity(entity, Velocity::default())
+        .unwrap();
+    world
+        .register_component_info(ComponentInfo::of::<Position>().with_debug::<Position>())
+        .unwrap();
+
+    // Ids are dense, in registration order, and survive re-registration.
+    assert_eq!(world.component_id::<Position>(), Some(ComponentId::new(0)));
+    assert_eq!(world.component_id::<Velocity>(), Some(ComponentId::new(1)));
+    let velocity = world.registry().get(ComponentId::new(1)).unwrap();
+    assert_eq!(velocity.hash(), Velocity::hash());
+    assert_eq!(
+        world.registry().id_of(Velocity::hash()),
+        Some(ComponentId::new(1))
+    );
+    assert!(world.entity_component::<Position>(entity).is_some());
+}
+
+#[test]
+fn spawn_batch_test() {
+    let mut world = World::new();
+    let first = world.alloc_entity();
+    let second = world.alloc_entity();
+    let third = world.alloc_entity();
+    world.dealloc_entity(second).unwrap();
+    world.tick();
+
+    // The single free id before `third` is too small, so the batch starts after it.
+    let entities = world
+        .spawn_batch((0..3).map(|index| {
+            (
+                Position {
+                    x: index as f32,
+                    y: 0.0,
+                },
+                Enemy,
+            )
+        }))
+        .unwrap();
+    assert_eq!(
+        entities,
+        vec![Entity::new(3), Entity::new(4), Entity::new(5)]
+    );
+    assert_eq!(world.alloc_entity().id(), second.id());
+    assert_eq!(world.alloc_entity(), Entity::new(6));
+    assert!(world.entity_component::<Position>(first).is_none());
+    assert!(world.entity_component::<Position>(third).is_none());
+
+    let positions: Vec<f32> = world
+        .query::<(&Position, &Enemy)>()
+        .map(|(_, (position, _))| position.x)
+        .collect();
+    assert_eq!(positions, vec![0.0, 1.0, 2.0]);
+
+    let spawned = world
+        .ecs_events_iter()
+        .filter(|event| matches!(event, events::ECSEvent::EntitySpawned(_)))
+        .count();
+    let added = world
+        .ecs_events_iter()
+        .filter(|event| matches!(event, events::ECSEvent::ComponentAdded(_, _)))
+        .count();
+    assert_eq!((spawned, added), (5, 6));
+
+    assert!(world
+        .spawn_batch(Vec::<(Enemy,)>::new())
+        .unwrap()
+        .is_empty());
+
+    // Required components and hooks run once the values are stored.
+    let tracked = world
+        .spawn_batch((0..2).map(|index| (Tracked(index), Projectile)))
+        .unwrap();
+    assert_eq!(
+        world.entity_component::<Indexed>(tracked[1]),
+        Some(&Indexed(1))
+    );
+    assert_eq!(
+        world.entity_component::<Visibility>(tracked[1]),
+        Some(&Visibility(false))
+    );
+
+    // Members of one exclusive group are enabled one by one, and a failing batch leaves none of
+    // its entities spawned.
+    let spawned = world
+        .spawn_batch((0..2).map(|_| (Idle, Walking { speed: 1.0 })))
+        .unwrap();
+    assert!(world.entity_component::<Idle>(spawned[1]).is_none());
+    assert!(world.entity_component::<Walking>(spawned[1]).is_some());
+    let entity_count = world.entity_count();
+    world
+        .set_exclusive_group("state", &[], ExclusivityPolicy::Reject)
+        .unwrap();
+    assert!(matches!(
+        world.spawn_batch((0..3).map(|_| (Idle, Walking { speed: 1.0 }))),
+        Err(WorldError::ExclusiveComponentConflict { .. })
+    ));
+    assert_eq!(world.entity_count(), entity_count);
+    assert_eq!(world.alloc_entity().id(), spawned[1].id() + 1);
+}
+
+fn clear_components(mut world: World) {
+    let mut entities = Vec::new();
+    for index in 0..10 {
+        let entity = world.alloc_entity();
+        world
+            .enable_component_for_entity(
+                entity,
+                Position {
+                    x: index as f32,
+                    y: 0.0,
+                },
+            )
+            .unwrap();
+        world.enable_component_for_entity(entity, Enemy).unwrap();
+        if index % 2 == 0 {
+            world
+                .enable_component_for_entity(entity, Velocity::default())
+                .unwrap();
+        }
+        entities.push(entity);
+    }
+    world.tick();
+
+    // Only the entities that also have a velocity lose their position.
+    let velocity = world.component_id::<Velocity>().unwrap();
+    let cleared = world.clear_component_filtered::<Position>(&[velocity], &[], true);
+    assert_eq!(cleared, 5);
+    let mut removed: Vec<f32> = world
+        .ecs_events_iter()
+        .filter_map(|event| match event {
+            events::ECSEvent::ComponentRemoved(_, old_data) => old_data
+                .as_any()
+                .downcast_ref::<Position>()
+                .map(|position| position.x),
+            _ => None,
+        })
+        .collect();
+    removed.sort_by(f32::total_cmp);
+    assert_eq!(removed, vec![0.0, 2.0, 4.0, 6.0, 8.0]);
+    assert!(world.entity_component::<Position>(entities[0]).is_none());
+    assert_eq!(
+        world
+            .entity_component::<Position>(entities[1])
+            .map(|position| position.x),
+        Some(1.0)
+    );
+    world.tick();
+
+    assert_eq!(world.clear_component::<Position>(false), 5);
+    assert_eq!(world.clear_component::<Enemy>(false), 10);
+    assert_eq!(world.ecs_events_iter().count(), 0);
+    assert!(world.query::<(&Position,)>().is_empty());
+    assert!(world.query::<(&Enemy,)>().is_empty());
+    assert_eq!(world.query::<(&Velocity,)>().count(), 5);
+    assert_eq!(
+        world
+            .component_column::<Position>()
+            .map(|column| column.len()),
+        Some(0)
+    );
+
+    // Cleared components can be added back.
+    world
+        .enable_component_for_entity(entities[3], Enemy)
+        .unwrap();
+    let enemies: Vec<Entity> = world
+        .query::<(&Enemy,)>()
+        .map(|(entity, _)| entity)
+        .collect();
+    assert_eq!(enemies, vec![entities[3]]);
+    assert_eq!(world.clear_component::<Idle>(true), 0);
+
+    world.dealloc_entity(entities[2]).unwrap();
+    world.dealloc_entity(entities[3]).unwrap();
+    assert_eq!(world.query::<(&Velocity,)>().count(), 4);
+    assert!(world.query::<(&Enemy,)>().is_empty());
+}
+
+#[test]
+fn clear_component_test() {
+    for_each_storage(clear_components);
+}
+
+#[test]
+fn entity_generation_test() {
+    let mut world = World::new();
+    let enemy = world.alloc_entity();
+    world
+        .enable_component_for_entity(enemy, Position::default())
+        .unwrap();
+    world.dealloc_entity(enemy).unwrap();
+    assert!(!world.is_alive(enemy));
+
+    // The id is reused, but the old handle doesn't reach the new entity.
+    let projectile = world.alloc_entity();
+    assert_eq!(projectile.id(), enemy.id());
+    assert_eq!(projectile.generation(), enemy.generation() + 1);
+    assert!(world.is_alive(projectile));
+    world
+        .enable_component_for_entity(projectile, Position { x: 1.0, y: 0.0 })
+        .unwrap();
+
+    assert!(world.entity_component::<Position>(enemy).is_none());
+    assert!(world.entity_component_mut::<Position>(enemy).is_none());
+    assert!(matches!(
+        world.enable_component_for_entity(enemy, Velocity::default()),
+        Err(WorldError::EntityNotAlive(entity)) if entity == enemy
+    ));
+    world.disable_component_for_entity::<Position>(enemy);
+    assert!(world.dealloc_entity(enemy).is_err());
+    assert!(world.is_alive(projectile));
+    assert_eq!(
+        world
+            .entity_component::<Position>(projectile)
+            .map(|position| position.x),
+        Some(1.0)
+    );
+
+    // Queries and columns hand out current handles.
+    let queried: Vec<Entity> = world
+        .query::<(&Position,)>()
+        .map(|(entity, _)| entity)
+        .collect();
+    assert_eq!(queried, vec![projectile]);
+    let stored: Vec<Entity> = world
+        .component_column::<Position>()
+        .unwrap()
+        .entities()
+        .collect();
+    assert_eq!(stored, vec![projectile]);
+    let column = world.component_column::<Position>().unwrap();
+    assert!(!column.contains(enemy));
+    assert!(column.get(enemy).is_none());
+    assert_eq!(column.get(projectile).map(|position| position.x), Some(1.0));
+    assert!(!world.is_alive(Entity::new(1)));
+}
+
+#[test]
+fn live_entities_test() {
+    let mut world = World::new();
+    let empty = world.alloc_entity();
+    let moving = world.alloc_entity();
+    world
+        .enable_component_for_entity(moving, Velocity::default())
+        .unwrap();
+    let batch = world.spawn_batch((0..2).map(|_| (Enemy,))).unwrap();
+
+    // Entities without components exist too.
+    assert_eq!(world.entity_count(), 4);
+    assert!(world.contains(empty));
+    let entities: Vec<Entity> = world.entities().collect();
+    assert_eq!(entities, vec![empty, moving, batch[0], batch[1]]);
+
+    world.dealloc_entity(empty).unwrap();
+    assert!(matches!(
+        world.dealloc_entity(empty),
+        Err(WorldError::EntityNotAlive(entity)) if entity == empty
+    ));
+    assert!(world.dealloc_entity(Entity::new(10)).is_err());
+    assert!(!world.contains(empty));
+    assert_eq!(world.entity_count(), 3);
+
+    let reused = world.alloc_entity();
+    let entities: Vec<Entity> = world.entities().collect();
+    assert_eq!(entities, vec![reused, moving, batch[0], batch[1]]);
+    assert_eq!(world.entity_count(), 4);
+}
+
+#[test]
+fn reserve_entity_test() {
+    let mut world = World::new();
+    let entities: Vec<Entity> = (0..4).map(|_| world.alloc_entity()).collect();
+    world
+        .enable_component_for_entity(entities[0], Enemy)
+        .unwrap();
+    world.dealloc_entity(entities[1]).unwrap();
+    world.tick();
+
+    // Reserving only needs a shared world, e.g. while it is being queried.
+    let mut projectiles = Vec::new();
+    for _ in world.query::<(&Enemy,)>() {
+        projectiles.push(world.reserve_entity());
+        projectiles.push(world.reserve_entity());
+    }
+    assert_eq!(projectiles[0].id(), entities[1].id());
+    assert_eq!(projectiles[0].generation(), 1);
+    assert_eq!(projectiles[1], Entity::new(4));
+    assert!(!world.is_alive(projectiles[0]));
+
+    // Reserved entities are spawned before anything else is allocated.
+    let next = world.alloc_entity();
+    assert_eq!(next, Entity::new(5));
+    assert!(projectiles
+        .iter()
+        .all(|projectile| world.is_alive(*projectile)));
+    let spawned: Vec<Entity> = world
+        .ecs_events_iter()
+        .filter_map(|event| match event {
+            events::ECSEvent::EntitySpawned(entity) => Some(*entity),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(spawned, vec![projectiles[0], projectiles[1], next]);
+
+    let projectile = world.reserve_entity();
+    world
+        .enable_component_for_entity(projectile, Velocity::default())
+        .unwrap();
+    assert_eq!(
+        world.entity_component::<Velocity>(projectile),
+        Some(&Velocity::default())
+    );
+    assert_eq!(world.entity_count(), 7);
+}
+
+#[test]
+fn spawn_at_test() {
+    let mut world = World::new();
+    let local = world.alloc_entity();
+
+    // Ids from a save or a server are claimed as they are.
+    let loaded = world.spawn_at(Entity::with_generation(5, 2)).unwrap();
+    assert!(world.is_alive(loaded));
+    assert!(matches!(
+        world.spawn_at(Entity::new(5)),
+        Err(WorldError::EntityIdTaken(5))
+    ));
+    assert!(matches!(
+        world.spawn_at(local),
+        Err(WorldError::EntityIdTaken(0))
+    ));
+    world.enable_component_for_entity(loaded, Enemy).unwrap();
+
+    // The server owns 100..200, the client allocates around it.
+    world.reserve_range_at(100..200).unwrap();
+    assert!(matches!(
+        world.reserve_range_at(4..8),
+        Err(WorldError::EntityIdTaken(5))
+    ));
+    assert!(matches!(
+        world.reserve_range_at(150..250),
+        Err(WorldError::EntityIdTaken(150))
+    ));
+    let client: Vec<usize> = (0..10).map(|_| world.alloc_entity().id()).collect();
+    assert_eq!(client, vec![1, 2, 3, 4, 6, 7, 8, 9, 10, 11]);
+    assert_eq!(world.reserve_range(90), 200..290);
+    assert_eq!(world.alloc_entity().id(), 12);
+
+    let server = world.spawn_at(Entity::new(150)).unwrap();
+    world.dealloc_entity(server).unwrap();
+    // Despawned server ids go back to the server's range.
+    let client: Vec<usize> = (0..87).map(|_| world.alloc_entity().id()).collect();
+    assert_eq!(client.last(), Some(&99));
+    assert_eq!(world.alloc_entity().id(), 290);
+    assert!(world.spawn_at(Entity::with_generation(150, 1)).is_ok());
+    assert!(world.spawn_at(Entity::new(250)).is_ok());
+
+    // Older generations would revive stale handles.
+    world.dealloc_entity(local).unwrap();
+    assert!(
+        matches!(world.spawn_at(local), Err(WorldError::StaleEntity(entity)) if entity == local)
+    );
+    assert!(!world.is_alive(local));
+    let respawned = world.spawn_at(Entity::with_generation(0, 3)).unwrap();
+    assert_eq!(respawned.generation(), 3);
+
+    // Ids past the largest one are rejected rather than sizing arrays after them.
+    assert!(matches!(
+        world.spawn_at(Entity::new(1 << 40)),
+        Err(WorldError::EntityIdOutOfRange(id)) if id == 1 << 40
+    ));
+    assert!(matches!(
+        world.spawn_at(Entity::new(usize::MAX)),
+        Err(WorldError::EntityIdOutOfRange(usize::MAX))
+    ));
+    assert!(matches!(
+        world.reserve_range_at(MAX_ENTITY_ID..MAX_ENTITY_ID + 2),
+        Err(WorldError::EntityIdOutOfRange(_))
+    ));
+    assert_eq!(world.entity_count(), 103);
+}
+
+#[test]
+fn entity_free_list_test() {
+    let mut world = World::new();
+    let entities: Vec<Entity> = (0..10).map(|_| world.alloc_entity()).collect();
+    assert_eq!(
+        world.entity_id_stats(),
+        EntityIdStats {
+            unbounded_from: 10,
+            ..Default::default()
+        }
+    );
+
+    for index in [1, 2, 3, 6, 9] {
+        world.dealloc_entity(entities[index]).unwrap();
+    }
+    // 9 merged into the unbounded range, 1..=3 into one run
+    assert_eq!(
+        world.entity_id_stats(),
+        EntityIdStats {
+            free_ranges: 2,
+            free_ids: 4,
+            largest_free_range: 3,
+            unbounded_from: 9,
+        }
+    );
+
+    // Freeing the gap between two runs merges them.
+    world.dealloc_entity(entities[4]).unwrap();
+    world.dealloc_entity(entities[5]).unwrap();
+    let stats = world.entity_id_stats();
+    assert_eq!((stats.free_ranges, stats.largest_free_range), (1, 6));
+
+    assert_eq!(
+        world.spawn_batch((0..2).map(|_| (Enemy,))).unwrap()[0].id(),
+        1
+    );
+    assert_eq!(world.alloc_entity().id(), 3);
+    world.reserve_range_at(5..7).unwrap();
+    let stats = world.entity_id_stats();
+    assert_eq!((stats.free_ranges, stats.free_ids), (1, 1));
+    assert_eq!(world.alloc_entity().id(), 4);
+    assert_eq!(world.alloc_entity().id(), 9);
+    assert_eq!(world.entity_id_stats().free_ranges, 0);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_query_test() {
+    use rayon::prelude::*;
+
+    let mut world = World::new();
+    for index in 0..1000 {
+        let entity = world.alloc_entity();
+        world
+            .enable_component_for_entity(
+                entity,
+                Position {
+                    x: index as f32,
+                    y: 0.0,
+                },
+            )
+            .unwrap();
+        if index % 2 == 0 {
+            world
+                .enable_component_for_entity(entity, Velocity { x: 1.0, y: 2.0 })
+                .unwrap();
+        }
+    }
+
+    world
+        .query_mut::<(&mut Position, &Velocity)>()
+        .par_iter()
+        .for_each(|(_, (position, velocity))| {
+            position.x += velocity.x;
+            position.y += velocity.y;
+        });
+
+    let moved: f32 = world
+        .query::<(&Position,)>()
+        .par_iter()
+        .map(|(_, (position,))| position.y)
+        .sum();
+    assert_eq!(moved, 1000.0);
+    let entity = Entity::new(10);
+    assert_eq!(
+        world
+            .entity_component::<Position>(entity)
+            .map(|position| position.x),
+        Some(11.0)
+    );
+    assert_eq!(world.query::<(&Enemy,)>().par_iter().count(), 0);
+
+    // The workers can reach the rest of the world through a shared borrow.
+    let world = &world;
+    let fast = world
+        .query::<(&Position,)>()
+        .par_iter()
+        .filter(|(entity, _)| world.entity_component::<Velocity>(*entity).is_some())
+        .count();
+    assert_eq!(fast, 500);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_reserve_entity_test() {
+    use rayon::prelude::*;
+
+    let mut world = World::new();
+    let entities: Vec<Entity> = (0..200).map(|_| world.alloc_entity()).collect();
+    for entity in &entities {
+        world.enable_component_for_entity(*entity, Enemy).unwrap();
+    }
+    // Every fourth id is freed, so the reserved ids span many ranges.
+    for entity in entities.iter().step_by(4) {
+        world.dealloc_entity(*entity).unwrap();
+    }
+    world.tick();
+
+    let mut projectiles: Vec<Entity> = world
+        .query::<(&Enemy,)>()
+        .par_iter()
+        .map(|_| world.reserve_entity())
+        .collect();
+    projectiles.sort_by_key(|projectile| projectile.id());
+    let ids: Vec<usize> = projectiles
+        .iter()
+        .map(|projectile| projectile.id())
+        .collect();
+    let expected: Vec<usize> = (0..200).step_by(4).chain(200..300).collect();
+    assert_eq!(ids, expected);
+
+    world.flush();
+    assert!(projectiles
+        .iter()
+        .all(|projectile| world.is_alive(*projectile)));
+    assert_eq!(world.alloc_entity(), Entity::new(300));
+}
+
 #[test]
+#[allow(unused_variables)]
 fn macro_test() {
     //use player::Health;
     let hash: HashSet<usize> = component_set!(player::Health, enemy::Health);